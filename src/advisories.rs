@@ -0,0 +1,356 @@
+use anyhow::{Context, Result, anyhow};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cargo_config::cargo_home;
+use crate::config::Config;
+use crate::registry::{CrateVersion, fetch_crate_versions};
+
+/// A RustSec advisory for one crate, reduced to what is needed to judge a version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advisory {
+    pub id: String,
+    pub title: String,
+    /// `unmaintained`, `unsound` or `notice` for advisories that are not
+    /// vulnerabilities
+    pub informational: Option<String>,
+    pub patched: Vec<VersionReq>,
+    pub unaffected: Vec<VersionReq>,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    informational: Option<String>,
+    #[serde(default)]
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+impl Advisory {
+    pub fn affects(&self, version: &Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(&self.unaffected)
+            .any(|req| req.matches(version))
+    }
+
+    fn is_vulnerability(&self) -> bool {
+        self.informational.is_none()
+    }
+
+    fn describe(&self) -> String {
+        let kind = self
+            .informational
+            .as_deref()
+            .map(|kind| format!(" ({kind})"))
+            .unwrap_or_default();
+        format!(
+            "{}{kind}: {} (https://rustsec.org/advisories/{}.html)",
+            self.id, self.title, self.id
+        )
+    }
+}
+
+/// Warn about advisories affecting `version` of `crate_name`, suggesting the nearest
+/// patched release that satisfies `requirement`. With `[advisories] strict = true`
+/// a vulnerability is an error rather than a warning.
+pub fn check_advisories(
+    crate_name: &str,
+    version: &Version,
+    requirement: Option<&VersionReq>,
+) -> Result<()> {
+    let config = Config::load()?.advisories;
+    let Some(db) = usable_db(advisory_db(config.db.clone()), config.strict)? else {
+        return Ok(());
+    };
+
+    let advisories = load_advisories(&db, crate_name)?;
+    let affecting: Vec<&Advisory> = advisories
+        .iter()
+        .filter(|advisory| advisory.affects(version))
+        .collect();
+    if affecting.is_empty() {
+        return Ok(());
+    }
+
+    let mut messages: Vec<String> = affecting
+        .iter()
+        .map(|advisory| {
+            format!(
+                "{crate_name} {version} is affected by {}",
+                advisory.describe()
+            )
+        })
+        .collect();
+    // Only look for a fix when there is something to fix; this needs the registry
+    let fix = fetch_crate_versions(crate_name)
+        .ok()
+        .and_then(|versions| nearest_patched(&versions, version, requirement, &advisories));
+    messages.push(match (fix, requirement) {
+        (Some(fix), _) => {
+            format!("{crate_name} {fix} is patched; run `cargox {crate_name}@={fix}`")
+        }
+        (None, Some(req)) => format!("no patched release of {crate_name} matches {req}"),
+        (None, None) => format!("no patched release of {crate_name} has been published"),
+    });
+
+    if config.strict && affecting.iter().any(|advisory| advisory.is_vulnerability()) {
+        return Err(anyhow!(
+            "refusing to use {crate_name} {version}: {}",
+            messages.join("; ")
+        ));
+    }
+    for message in messages {
+        eprintln!("warning: {message}");
+    }
+    Ok(())
+}
+
+/// The configured database, or `$CARGO_HOME/advisory-db`.
+fn advisory_db(configured: Option<PathBuf>) -> Option<PathBuf> {
+    configured.or_else(|| cargo_home().map(|home| home.join("advisory-db")))
+}
+
+/// `db` if it is a checkout. A missing database skips the check with a warning, or
+/// is an error under `strict`, whether it was configured or not.
+fn usable_db(db: Option<PathBuf>, strict: bool) -> Result<Option<PathBuf>> {
+    if let Some(db) = db.as_ref().filter(|db| db.is_dir()) {
+        return Ok(Some(db.clone()));
+    }
+    let location = db.map_or_else(
+        || "$CARGO_HOME/advisory-db".to_owned(),
+        |db| db.display().to_string(),
+    );
+    if strict {
+        return Err(anyhow!(
+            "advisory database not found at {location}; clone \
+             https://github.com/rustsec/advisory-db there or change `[advisories] db`"
+        ));
+    }
+    eprintln!("warning: advisory database not found at {location}; skipping the advisory check");
+    Ok(None)
+}
+
+/// Read the advisories for `crate_name` from `crates/<crate>/` in the database,
+/// skipping withdrawn ones.
+fn load_advisories(db: &Path, crate_name: &str) -> Result<Vec<Advisory>> {
+    let dir = db.join("crates").join(crate_name);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", dir.display())),
+    };
+
+    let mut advisories = Vec::new();
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("failed to read {}", dir.display()))?
+            .path();
+        if !matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("md" | "toml")
+        ) {
+            continue;
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if let Some(advisory) = parse_advisory(&contents)
+            .with_context(|| format!("failed to parse advisory {}", path.display()))?
+        {
+            advisories.push(advisory);
+        }
+    }
+    advisories.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(advisories)
+}
+
+/// Parse an advisory in the current Markdown format, whose metadata is a fenced
+/// ```` ```toml ```` block followed by a `# Title`, or in the older plain TOML format.
+/// Withdrawn advisories yield `None`.
+fn parse_advisory(contents: &str) -> Result<Option<Advisory>> {
+    let (metadata, body) = match contents.trim_start().strip_prefix("```toml") {
+        Some(rest) => rest
+            .split_once("\n```")
+            .ok_or_else(|| anyhow!("unterminated ```toml block"))?,
+        None => (contents, ""),
+    };
+    let file: AdvisoryFile = toml::from_str(metadata)?;
+    if file.advisory.withdrawn.is_some() {
+        return Ok(None);
+    }
+
+    let title = file
+        .advisory
+        .title
+        .or_else(|| {
+            body.lines()
+                .find_map(|line| line.trim().strip_prefix("# "))
+                .map(|title| title.trim().to_string())
+        })
+        .unwrap_or_default();
+    let parse_reqs = |reqs: &[String]| -> Result<Vec<VersionReq>> {
+        reqs.iter()
+            .map(|req| {
+                VersionReq::parse(req).with_context(|| format!("invalid version range `{req}`"))
+            })
+            .collect()
+    };
+
+    Ok(Some(Advisory {
+        patched: parse_reqs(&file.versions.patched)?,
+        unaffected: parse_reqs(&file.versions.unaffected)?,
+        id: file.advisory.id,
+        title,
+        informational: file.advisory.informational,
+    }))
+}
+
+/// The lowest published release above `current` that matches `requirement` and is
+/// not affected by any of the advisories.
+fn nearest_patched(
+    versions: &[CrateVersion],
+    current: &Version,
+    requirement: Option<&VersionReq>,
+    advisories: &[Advisory],
+) -> Option<Version> {
+    versions
+        .iter()
+        .filter(|entry| !entry.yanked)
+        .filter_map(|entry| Version::parse(&entry.num).ok())
+        .filter(|version| version > current)
+        .filter(|version| requirement.is_none_or(|req| req.matches(version)))
+        .filter(|version| !advisories.iter().any(|advisory| advisory.affects(version)))
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADVISORY: &str = r#"```toml
+[advisory]
+id = "RUSTSEC-2024-0001"
+package = "tool"
+date = "2024-01-01"
+categories = ["code-execution"]
+
+[versions]
+patched = [">= 1.4.2, < 2.0.0", ">= 2.1.0"]
+unaffected = ["< 1.0.0"]
+```
+
+# Arbitrary code execution in tool
+
+Details follow.
+"#;
+
+    fn version(num: &str) -> CrateVersion {
+        CrateVersion {
+            num: num.to_string(),
+            yanked: false,
+            rust_version: None,
+            created_at: None,
+            checksum: None,
+        }
+    }
+
+    #[test]
+    fn parse_advisory_reads_markdown_and_toml_formats() {
+        let advisory = parse_advisory(ADVISORY).unwrap().unwrap();
+        assert_eq!(advisory.id, "RUSTSEC-2024-0001");
+        assert_eq!(advisory.title, "Arbitrary code execution in tool");
+        assert!(advisory.is_vulnerability());
+        assert!(advisory.affects(&Version::new(1, 4, 1)));
+        assert!(advisory.affects(&Version::new(2, 0, 0)));
+        assert!(!advisory.affects(&Version::new(1, 4, 2)));
+        assert!(!advisory.affects(&Version::new(0, 9, 0)));
+
+        let legacy = parse_advisory(
+            "[advisory]\nid = \"RUSTSEC-2019-0001\"\npackage = \"tool\"\ntitle = \"Old\"\ninformational = \"unmaintained\"\n",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(legacy.title, "Old");
+        assert!(!legacy.is_vulnerability());
+        assert!(legacy.affects(&Version::new(9, 9, 9)));
+
+        let withdrawn = ADVISORY.replace("date =", "withdrawn = \"2024-02-01\"\ndate =");
+        assert_eq!(parse_advisory(&withdrawn).unwrap(), None);
+        assert!(parse_advisory("```toml\n[advisory]\n").is_err());
+    }
+
+    #[test]
+    fn load_advisories_reads_the_crate_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("crates").join("tool");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("RUSTSEC-2024-0001.md"), ADVISORY).unwrap();
+        fs::write(dir.join("README.txt"), "not an advisory").unwrap();
+
+        let advisories = load_advisories(temp.path(), "tool").unwrap();
+        assert_eq!(advisories.len(), 1);
+        assert!(load_advisories(temp.path(), "other").unwrap().is_empty());
+    }
+
+    #[test]
+    fn usable_db_fails_closed_under_strict() {
+        let temp = tempfile::tempdir().unwrap();
+        let missing = temp.path().join("advisory-db");
+
+        let err = usable_db(None, true).unwrap_err();
+        assert!(err.to_string().contains("advisory database not found"));
+        assert!(usable_db(Some(missing.clone()), true).is_err());
+        assert_eq!(usable_db(Some(missing), false).unwrap(), None);
+        assert_eq!(
+            usable_db(Some(temp.path().to_path_buf()), true).unwrap(),
+            Some(temp.path().to_path_buf())
+        );
+    }
+
+    #[test]
+    fn nearest_patched_respects_the_requirement() {
+        let advisories = vec![parse_advisory(ADVISORY).unwrap().unwrap()];
+        let versions: Vec<CrateVersion> = ["1.4.0", "1.4.1", "1.4.2", "1.5.0", "2.0.0", "2.1.0"]
+            .into_iter()
+            .map(version)
+            .collect();
+        let current = Version::new(1, 4, 0);
+
+        assert_eq!(
+            nearest_patched(&versions, &current, None, &advisories),
+            Some(Version::new(1, 4, 2))
+        );
+        let two = VersionReq::parse("2").unwrap();
+        assert_eq!(
+            nearest_patched(&versions, &Version::new(2, 0, 0), Some(&two), &advisories),
+            Some(Version::new(2, 1, 0))
+        );
+        let pinned = VersionReq::parse("~1.4.0, <1.4.2").unwrap();
+        assert_eq!(
+            nearest_patched(&versions, &current, Some(&pinned), &advisories),
+            None
+        );
+    }
+}
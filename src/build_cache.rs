@@ -0,0 +1,280 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::cli::CacheAction;
+use crate::config::Config;
+use crate::paths::{create_store_dir, get_install_dir};
+use crate::toolchain::Toolchain;
+
+/// Touched whenever a cache entry is used, so eviction can find the stalest one.
+const LAST_USED_FILE: &str = ".cargox-last-used";
+
+/// One persistent target directory, shared by every source build with the same
+/// compiler and host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+pub fn cache_root() -> Result<PathBuf> {
+    Ok(get_install_dir()?.join("cache").join("build"))
+}
+
+/// The target directory for builds with `toolchain`. Artifacts from different
+/// compilers can never be reused, so each rustc release gets its own directory.
+pub fn target_dir(toolchain: &Toolchain) -> Result<PathBuf> {
+    let dir = cache_root()?.join(entry_name(toolchain));
+    create_store_dir(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    fs::write(dir.join(LAST_USED_FILE), b"")
+        .with_context(|| format!("failed to mark {} as used", dir.display()))?;
+    Ok(dir)
+}
+
+fn entry_name(toolchain: &Toolchain) -> String {
+    format!("{}-rustc-{}", toolchain.host, toolchain.rustc)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn entries() -> Result<Vec<CacheEntry>> {
+    let root = cache_root()?;
+    let read_dir = match fs::read_dir(&root) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", root.display()));
+        }
+    };
+
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let last_used = fs::metadata(path.join(LAST_USED_FILE))
+            .or_else(|_| fs::metadata(&path))
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        entries.push(CacheEntry {
+            size: dir_size(&path),
+            path,
+            last_used,
+        });
+    }
+    entries.sort_by_key(|entry| entry.last_used);
+    Ok(entries)
+}
+
+/// Pick the least recently used entries to delete until the rest fit in `max_bytes`.
+fn select_evictions(entries: &[CacheEntry], max_bytes: u64) -> Vec<&CacheEntry> {
+    let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let mut oldest_first: Vec<&CacheEntry> = entries.iter().collect();
+    oldest_first.sort_by_key(|entry| entry.last_used);
+
+    oldest_first
+        .into_iter()
+        .take_while(|entry| {
+            let evict = total > max_bytes;
+            total = total.saturating_sub(entry.size);
+            evict
+        })
+        .collect()
+}
+
+/// Shrink the cache to `max_bytes`, returning the entries that were removed.
+pub fn enforce_limit(max_bytes: u64) -> Result<Vec<CacheEntry>> {
+    let entries = entries()?;
+    let evicted: Vec<CacheEntry> = select_evictions(&entries, max_bytes)
+        .into_iter()
+        .cloned()
+        .collect();
+    for entry in &evicted {
+        fs::remove_dir_all(&entry.path)
+            .with_context(|| format!("failed to remove {}", entry.path.display()))?;
+    }
+    Ok(evicted)
+}
+
+/// Trim the cache after a build. Failing to trim never fails the install.
+pub fn trim_after_build(config: &Config) {
+    if !config.build_cache.enabled {
+        return;
+    }
+    let result = config.build_cache.max_bytes().and_then(enforce_limit);
+    match result {
+        Ok(evicted) => {
+            for entry in evicted {
+                eprintln!(
+                    "Evicted build cache {} ({})",
+                    entry.path.display(),
+                    format_size(entry.size)
+                );
+            }
+        }
+        Err(err) => eprintln!("warning: failed to trim build cache: {err:#}"),
+    }
+}
+
+/// Handle `cargox --cache <action>`.
+pub fn run_cache_command(action: CacheAction) -> Result<()> {
+    let config = Config::load()?;
+    let max_bytes = config.build_cache.max_bytes()?;
+
+    match action {
+        CacheAction::Info => {
+            let entries = entries()?;
+            let total: u64 = entries.iter().map(|entry| entry.size).sum();
+            println!(
+                "Build cache: {} ({})",
+                cache_root()?.display(),
+                if config.build_cache.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+            for entry in &entries {
+                println!("  {}  {}", format_size(entry.size), entry.path.display());
+            }
+            println!(
+                "Total: {} of {} limit",
+                format_size(total),
+                format_size(max_bytes)
+            );
+        }
+        CacheAction::Prune => {
+            let evicted = enforce_limit(max_bytes)?;
+            let freed: u64 = evicted.iter().map(|entry| entry.size).sum();
+            println!(
+                "Removed {} cache entries, freeing {}",
+                evicted.len(),
+                format_size(freed)
+            );
+        }
+        CacheAction::Clean => {
+            let evicted = enforce_limit(0)?;
+            let freed: u64 = evicted.iter().map(|entry| entry.size).sum();
+            println!("Removed the build cache, freeing {}", format_size(freed));
+        }
+    }
+
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(read_dir) = fs::read_dir(path) else {
+        return 0;
+    };
+    read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::env_lock;
+    use semver::Version;
+    use std::env;
+    use std::time::Duration;
+
+    fn entry(name: &str, size: u64, age_secs: u64) -> CacheEntry {
+        CacheEntry {
+            path: PathBuf::from(name),
+            size,
+            last_used: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 - age_secs),
+        }
+    }
+
+    #[test]
+    fn select_evictions_removes_least_recently_used() {
+        let entries = vec![
+            entry("new", 400, 10),
+            entry("old", 300, 1000),
+            entry("middle", 300, 100),
+        ];
+
+        let evicted: Vec<_> = select_evictions(&entries, 700)
+            .into_iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+        assert_eq!(evicted, vec![PathBuf::from("old")]);
+
+        assert!(select_evictions(&entries, 1000).is_empty());
+        assert_eq!(select_evictions(&entries, 0).len(), 3);
+    }
+
+    #[test]
+    fn target_dir_is_keyed_by_host_and_rustc() {
+        let _guard = env_lock().lock().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        unsafe {
+            env::set_var("CARGOX_INSTALL_DIR", temp.path());
+        }
+
+        let toolchain = Toolchain {
+            name: Some("nightly".to_string()),
+            rustc: Version::parse("1.97.0-nightly").unwrap(),
+            host: "x86_64-unknown-linux-gnu".to_string(),
+        };
+        let dir = target_dir(&toolchain).unwrap();
+        fs::write(dir.join("artifact"), vec![0u8; 2048]).unwrap();
+        let listed = entries().unwrap();
+        let evicted = enforce_limit(0).unwrap();
+
+        unsafe {
+            env::remove_var("CARGOX_INSTALL_DIR");
+        }
+
+        assert_eq!(
+            dir,
+            temp.path()
+                .join("cache")
+                .join("build")
+                .join("x86_64-unknown-linux-gnu-rustc-1.97.0-nightly")
+        );
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].size >= 2048);
+        assert_eq!(evicted.len(), 1);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn format_size_uses_binary_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(10 << 30), "10.0 GiB");
+    }
+}
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result, anyhow};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Once;
+use toml::{Table, Value};
+
+/// Tables of the user's cargo configuration that control where crates are fetched
+/// from. Everything else (notably `[install]` and `[build]`) stays out of the sandbox.
+const CARRIED_TABLES: [&str; 5] = ["source", "registries", "registry", "net", "http"];
+
+/// The subset of the user's cargo configuration carried into sandboxed installs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CargoConfig {
+    table: Table,
+}
+
+/// Where crates.io packages actually come from once source replacement is applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CratesIoSource {
+    /// A `cargo vendor` style directory of unpacked crates
+    Directory(PathBuf),
+    /// A `local-registry` of `.crate` files
+    LocalRegistry(PathBuf),
+    /// A remote registry index, e.g. `sparse+https://mirror.example.com/index/`
+    Registry(String),
+}
+
+impl CargoConfig {
+    /// Load `$CARGO_HOME/config.toml`. `cargo install` only reads configuration from
+    /// `CARGO_HOME`, which the sandbox unsets, so this is the file we have to carry over.
+    pub fn load() -> Result<Self> {
+        let Some(cargo_home) = cargo_home() else {
+            return Ok(Self::default());
+        };
+
+        for name in ["config.toml", "config"] {
+            let path = cargo_home.join(name);
+            if path.is_file() {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let base = cargo_home.parent().unwrap_or(&cargo_home);
+                return Self::parse(&contents, base)
+                    .with_context(|| format!("failed to parse {}", path.display()));
+            }
+        }
+
+        Ok(Self::default())
+    }
+
+    /// Parse a cargo config file, keeping only the carried tables and making source
+    /// paths absolute. Cargo resolves them against the parent of the directory holding
+    /// the config file, which is `base`.
+    fn parse(contents: &str, base: &Path) -> Result<Self> {
+        let mut parsed: Table = contents.parse()?;
+        let mut table = Table::new();
+        for name in CARRIED_TABLES {
+            if let Some(value) = parsed.remove(name) {
+                table.insert(name.to_owned(), value);
+            }
+        }
+
+        if let Some(Value::Table(sources)) = table.get_mut("source") {
+            for (_, source) in sources.iter_mut() {
+                let Value::Table(source) = source else {
+                    continue;
+                };
+                for key in ["directory", "local-registry"] {
+                    if let Some(Value::String(path)) = source.get_mut(key) {
+                        *path = base.join(&*path).to_string_lossy().into_owned();
+                    }
+                }
+            }
+        }
+
+        Ok(Self { table })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Pass the carried settings to a cargo command with `--config <file>`, writing the
+    /// file into `scratch_dir`.
+    pub fn apply(&self, cmd: &mut Command, scratch_dir: &Path) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let path = scratch_dir.join("cargox-config.toml");
+        let contents = toml::to_string(&self.table).context("failed to serialize cargo config")?;
+        fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        cmd.arg("--config");
+        cmd.arg(path);
+        Ok(())
+    }
+
+    /// Follow `[source.crates-io] replace-with` chains to the source that really serves
+    /// crates.io packages. Returns `None` when crates.io is not replaced.
+    pub fn crates_io_source(&self) -> Result<Option<CratesIoSource>> {
+        let Some(Value::Table(sources)) = self.table.get("source") else {
+            return Ok(None);
+        };
+
+        let mut name = "crates-io".to_owned();
+        let mut replaced = false;
+        // A chain can never be longer than the number of sources without looping
+        for _ in 0..=sources.len() {
+            let source = sources.get(&name).and_then(Value::as_table);
+            if let Some(next) = source
+                .and_then(|source| source.get("replace-with"))
+                .and_then(Value::as_str)
+            {
+                name = next.to_owned();
+                replaced = true;
+                continue;
+            }
+
+            if !replaced {
+                return Ok(None);
+            }
+
+            let source = source.ok_or_else(|| {
+                anyhow!("crates-io is replaced with `{name}`, but [source.{name}] is not defined")
+            })?;
+            let string = |key: &str| source.get(key).and_then(Value::as_str);
+            return if let Some(dir) = string("directory") {
+                Ok(Some(CratesIoSource::Directory(PathBuf::from(dir))))
+            } else if let Some(dir) = string("local-registry") {
+                Ok(Some(CratesIoSource::LocalRegistry(PathBuf::from(dir))))
+            } else if let Some(index) = string("registry") {
+                Ok(Some(CratesIoSource::Registry(index.to_owned())))
+            } else {
+                Err(anyhow!("unsupported source replacement [source.{name}]"))
+            };
+        }
+
+        Err(anyhow!(
+            "cyclic `replace-with` in cargo source configuration"
+        ))
+    }
+}
+
+/// The source replacing crates.io, for registry lookups and for choosing between
+/// `cargo-binstall` and `cargo install`. Neither needs the cargo config as such, so
+/// one that cannot be read is reported once and treated as not replacing crates.io;
+/// the error is only fatal once `cargo install` has to run with it.
+pub fn crates_io_replacement() -> Option<CratesIoSource> {
+    replacement_or_warn(CargoConfig::load().and_then(|config| config.crates_io_source()))
+}
+
+fn replacement_or_warn(source: Result<Option<CratesIoSource>>) -> Option<CratesIoSource> {
+    static WARNED: Once = Once::new();
+    source.unwrap_or_else(|err| {
+        WARNED.call_once(|| eprintln!("warning: ignoring cargo config: {err:#}"));
+        None
+    })
+}
+
+/// `$CARGO_HOME`, defaulting to `~/.cargo` like cargo itself.
+pub fn cargo_home() -> Option<PathBuf> {
+    if let Some(home) = env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(home));
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".cargo"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VENDORED: &str = r#"
+[source.crates-io]
+replace-with = "vendored"
+
+[source.vendored]
+directory = "vendor"
+
+[net]
+git-fetch-with-cli = true
+
+[install]
+root = "/somewhere/else"
+"#;
+
+    #[test]
+    fn parse_keeps_only_fetch_settings() {
+        let config = CargoConfig::parse(VENDORED, Path::new("/home/user")).unwrap();
+        assert!(config.table.contains_key("source"));
+        assert!(config.table.contains_key("net"));
+        assert!(!config.table.contains_key("install"));
+    }
+
+    #[test]
+    fn crates_io_source_follows_replacement() {
+        let config = CargoConfig::parse(VENDORED, Path::new("/home/user")).unwrap();
+        assert_eq!(
+            config.crates_io_source().unwrap(),
+            Some(CratesIoSource::Directory(
+                Path::new("/home/user").join("vendor")
+            ))
+        );
+
+        let mirror = r#"
+[source.crates-io]
+replace-with = "corp"
+
+[source.corp]
+registry = "sparse+https://mirror.example.com/index/"
+"#;
+        let config = CargoConfig::parse(mirror, Path::new("/")).unwrap();
+        assert_eq!(
+            config.crates_io_source().unwrap(),
+            Some(CratesIoSource::Registry(
+                "sparse+https://mirror.example.com/index/".to_string()
+            ))
+        );
+
+        assert_eq!(CargoConfig::default().crates_io_source().unwrap(), None);
+    }
+
+    #[test]
+    fn replacement_ignores_unreadable_configs() {
+        let broken = CargoConfig::parse("[source.crates-io", Path::new("/home/user"));
+        assert!(broken.is_err());
+        assert_eq!(
+            replacement_or_warn(broken.and_then(|config| config.crates_io_source())),
+            None
+        );
+
+        let vendored = CargoConfig::parse(VENDORED, Path::new("/home/user")).unwrap();
+        assert_eq!(
+            replacement_or_warn(vendored.crates_io_source()),
+            Some(CratesIoSource::Directory(PathBuf::from(
+                "/home/user/vendor"
+            )))
+        );
+    }
+
+    #[test]
+    fn crates_io_source_rejects_cycles() {
+        let cyclic = r#"
+[source.crates-io]
+replace-with = "a"
+
+[source.a]
+replace-with = "b"
+
+[source.b]
+replace-with = "a"
+"#;
+        let config = CargoConfig::parse(cyclic, Path::new("/")).unwrap();
+        assert!(config.crates_io_source().is_err());
+    }
+}
@@ -1,20 +1,28 @@
 use anyhow::{Result, anyhow};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::timestamp::parse_instant;
 
 /// Run Cargo binaries on demand, installing them via `cargo-binstall` when missing.
 #[derive(Parser, Debug)]
 #[command(name = "cargox", author, version, about, long_about = None, arg_required_else_help = true)]
 pub struct Cli {
-    /// Crate to run, optionally suffixed with `@version`
-    #[arg(value_name = "crate[@version]")]
-    pub crate_spec: String,
+    /// Crate to run, optionally followed by `:bin` and `@version`, a
+    /// `[crate@]git+<url>[#ref]` repository, or a local path such as `./tools/codegen`
+    #[arg(value_name = "crate[:bin][@version]", required_unless_present_any = ["path", "cache", "list"])]
+    pub crate_spec: Option<String>,
 
     /// Execute this binary from the crate (defaults to crate name)
     #[arg(long, value_name = "NAME")]
     pub bin: Option<String>,
 
+    /// Build the crate at this local path; a crate spec then selects a workspace member
+    #[arg(long, value_name = "PATH")]
+    pub path: Option<PathBuf>,
+
     /// Force reinstall; ignore any existing binary
     #[arg(short, long)]
     pub force: bool,
@@ -27,11 +35,65 @@ pub struct Cli {
     #[arg(short = 's', long)]
     pub build_from_source: bool,
 
+    /// Rust toolchain for source builds (also accepted as a leading `+toolchain`);
+    /// implies `--build-from-source`
+    #[arg(long, value_name = "NAME")]
+    pub toolchain: Option<String>,
+
+    /// Pick the newest release whose `rust-version` the local rustc supports
+    /// (automatic when building from source)
+    #[arg(long)]
+    pub respect_rust_version: bool,
+
+    /// Consider prereleases when picking a version (`crate@next` implies this)
+    #[arg(long)]
+    pub pre: bool,
+
+    /// Pick the newest release published before this date (`YYYY-MM-DD`, midnight
+    /// UTC) or RFC 3339 timestamp, like a `crate@<YYYY-MM-DD` bound
+    #[arg(long, value_name = "DATE", value_parser = parse_as_of)]
+    pub as_of: Option<i64>,
+
+    /// Install for this target triple instead of the host, e.g. `aarch64-unknown-linux-musl`
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+
+    /// Run a binary installed with `--target` even though it does not match the host
+    #[arg(long, requires = "target")]
+    pub allow_foreign: bool,
+
+    /// Always verify the binary against the checksum recorded at install time, and
+    /// refuse binaries without one
+    #[arg(long)]
+    pub paranoid: bool,
+
+    /// Install crates that cargox has not installed before without asking
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// List installed binaries with their crate and target triple
+    #[arg(long, exclusive = true)]
+    pub list: bool,
+
+    /// Manage the build cache for source installs instead of running a crate
+    #[arg(long, value_name = "ACTION", exclusive = true)]
+    pub cache: Option<CacheAction>,
+
     /// Arguments passed to the executed binary (use `--` to delimit)
     #[arg(trailing_var_arg = true, value_name = "binary-args")]
     pub args: Vec<OsString>,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheAction {
+    /// Show cached target directories and their sizes
+    Info,
+    /// Evict least recently used entries until the cache fits its size limit
+    Prune,
+    /// Remove the whole build cache
+    Clean,
+}
+
 impl Cli {
     /// Parse arguments, ensuring that arguments after the crate spec are passed to the binary
     /// rather than being intercepted by clap. This allows `cargox bat --help` to show bat's
@@ -43,51 +105,24 @@ impl Cli {
         if args.is_empty() {
             return Err(anyhow!("no program name in arguments"));
         }
-        args.remove(0);
-
-        // Find the first positional argument (crate spec) by iterating through args
-        // and stopping at the first argument that doesn't start with `-` and isn't a value for a flag
-        let mut crate_spec_idx = None;
-        let mut i = 0;
-        let mut skip_next = false;
-
-        while i < args.len() {
-            if skip_next {
-                skip_next = false;
-                i += 1;
-                continue;
-            }
-
-            let arg = args[i].to_string_lossy();
-
-            // Check if this is a flag that takes a value
-            if arg == "--bin" {
-                skip_next = true;
-                i += 1;
-                continue;
-            }
-
-            // If it doesn't start with `-`, it's the crate spec
-            if !arg.starts_with('-') {
-                crate_spec_idx = Some(i);
-                break;
-            }
+        let program = args.remove(0);
 
-            i += 1;
+        // Installed as `cargo-x`, cargox is run by `cargo x <crate>` as `cargo-x x <crate>`
+        if is_cargo_x(&program) && args.first().is_some_and(|arg| arg == "x") {
+            args.remove(0);
         }
 
-        // If we found a crate spec, split args at that point
-        let (cargox_args, binary_args) = if let Some(idx) = crate_spec_idx {
-            let mut cargox_args = args[..idx].to_vec();
-            // Add the crate spec to cargox args
-            cargox_args.push(args[idx].clone());
-            // Everything after crate spec goes to the binary
-            let binary_args = args[idx + 1..].to_vec();
-            (cargox_args, binary_args)
-        } else {
-            // No crate spec found, let clap handle it (will show help or error)
-            (args, vec![])
-        };
+        // Accept rustup's `+toolchain` shorthand as the first argument, like cargo does
+        if let Some(toolchain) = args
+            .first()
+            .and_then(|arg| arg.to_str())
+            .and_then(|arg| arg.strip_prefix('+'))
+        {
+            let toolchain = OsString::from(toolchain);
+            args.splice(0..1, [OsString::from("--toolchain"), toolchain]);
+        }
+
+        let (cargox_args, binary_args) = split_binary_args(args);
 
         // Parse cargox arguments with clap
         let mut cli =
@@ -102,9 +137,162 @@ impl Cli {
 
         // Set the binary arguments
         cli.args = binary_args;
+        cli.expand_cargo_shorthand();
 
         Ok(cli)
     }
+
+    /// `cargox cargo deny check` runs `cargo-deny`, just as `cargo deny check` does. A
+    /// spec with a version, such as `cargo@0.80`, still names the `cargo` crate, and so
+    /// does a built-in command like `cargox cargo build`: crates such as `cargo-build`
+    /// are unrelated to it.
+    fn expand_cargo_shorthand(&mut self) {
+        if self.crate_spec.as_deref() != Some("cargo") || self.path.is_some() {
+            return;
+        }
+        let Some(subcommand) = self.args.first().and_then(|arg| arg.to_str()) else {
+            return;
+        };
+        let name = subcommand.split('@').next().unwrap_or_default();
+        if name.is_empty() || subcommand.starts_with('-') || CARGO_BUILTINS.contains(&name) {
+            return;
+        }
+        self.crate_spec = Some(format!("cargo-{subcommand}"));
+        self.args.remove(0);
+    }
+}
+
+/// Commands cargo runs itself, including their aliases, and the ones rustup ships
+/// alongside it. These are never looked up as `cargo-*` crates.
+const CARGO_BUILTINS: &[&str] = &[
+    "add",
+    "b",
+    "bench",
+    "build",
+    "c",
+    "check",
+    "clean",
+    "clippy",
+    "config",
+    "d",
+    "doc",
+    "fetch",
+    "fix",
+    "fmt",
+    "generate-lockfile",
+    "help",
+    "info",
+    "init",
+    "install",
+    "locate-project",
+    "login",
+    "logout",
+    "metadata",
+    "miri",
+    "new",
+    "owner",
+    "package",
+    "pkgid",
+    "publish",
+    "r",
+    "read-manifest",
+    "remove",
+    "report",
+    "rm",
+    "run",
+    "rustc",
+    "rustdoc",
+    "search",
+    "t",
+    "test",
+    "tree",
+    "uninstall",
+    "update",
+    "vendor",
+    "verify-project",
+    "version",
+    "yank",
+];
+
+/// Split arguments at the crate spec: what precedes it, and the spec itself, is for
+/// cargox; what follows is for the binary. A `--` right after the spec is dropped, as
+/// `cargo run -- args` does, so `cargox tool -- args` and `cargox --path tool -- args`
+/// pass the same arguments.
+fn split_binary_args(args: Vec<OsString>) -> (Vec<OsString>, Vec<OsString>) {
+    // Find the first positional argument (crate spec) by iterating through args
+    // and stopping at the first argument that doesn't start with `-` and isn't a value for a flag
+    let mut crate_spec_idx = None;
+    let mut separator_idx = None;
+    let mut i = 0;
+    let mut skip_next = false;
+
+    while i < args.len() {
+        if skip_next {
+            skip_next = false;
+            i += 1;
+            continue;
+        }
+
+        let arg = args[i].to_string_lossy();
+
+        // An explicit `--` ends cargox's arguments even without a crate spec,
+        // e.g. `cargox --path tools/codegen -- args`
+        if arg == "--" {
+            separator_idx = Some(i);
+            break;
+        }
+
+        // Check if this is a flag that takes a value
+        if arg == "--bin"
+            || arg == "--path"
+            || arg == "--toolchain"
+            || arg == "--cache"
+            || arg == "--target"
+            || arg == "--as-of"
+        {
+            skip_next = true;
+            i += 1;
+            continue;
+        }
+
+        // If it doesn't start with `-`, it's the crate spec
+        if !arg.starts_with('-') {
+            crate_spec_idx = Some(i);
+            break;
+        }
+
+        i += 1;
+    }
+
+    // If we found a crate spec, split args at that point
+    if let Some(idx) = crate_spec_idx {
+        let mut cargox_args = args[..idx].to_vec();
+        // Add the crate spec to cargox args
+        cargox_args.push(args[idx].clone());
+        // Everything after crate spec goes to the binary, less a leading separator
+        let mut rest = idx + 1;
+        if args.get(rest).is_some_and(|arg| arg == "--") {
+            rest += 1;
+        }
+        (cargox_args, args[rest..].to_vec())
+    } else if let Some(idx) = separator_idx {
+        (args[..idx].to_vec(), args[idx + 1..].to_vec())
+    } else {
+        // No crate spec found, let clap handle it (will show help or error)
+        (args, vec![])
+    }
+}
+
+/// Whether cargox was started under the name cargo gives the `x` subcommand.
+fn is_cargo_x(program: &OsStr) -> bool {
+    Path::new(program)
+        .file_stem()
+        .is_some_and(|stem| stem == "cargo-x")
+}
+
+fn parse_as_of(value: &str) -> Result<i64, String> {
+    parse_instant(value)
+        .ok_or_else(|| "expected a date such as 2024-03-01 or an RFC 3339 timestamp".to_string())
 }
 
 #[cfg(test)]
@@ -126,15 +314,111 @@ mod tests {
     #[test]
     fn parse_args_handles_bin_flag() {
         let cli = Cli::try_parse_from(["cargox", "--bin", "foo", "mycrate"]).unwrap();
-        assert_eq!(cli.crate_spec, "mycrate");
+        assert_eq!(cli.crate_spec.as_deref(), Some("mycrate"));
         assert_eq!(cli.bin, Some("foo".to_string()));
         assert_eq!(cli.args.len(), 0);
     }
 
+    #[test]
+    fn split_binary_args_drops_a_separator_after_the_spec() {
+        let os = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        let split = |args: &[&str]| split_binary_args(os(args));
+
+        assert_eq!(
+            split(&["--bin", "x", "tool", "--help"]),
+            (os(&["--bin", "x", "tool"]), os(&["--help"]))
+        );
+        assert_eq!(
+            split(&["./tools/codegen", "--", "--out", "gen"]),
+            (os(&["./tools/codegen"]), os(&["--out", "gen"]))
+        );
+        assert_eq!(
+            split(&["--path", "tools/codegen", "--", "--out", "gen"]),
+            (os(&["--path", "tools/codegen"]), os(&["--out", "gen"]))
+        );
+        assert_eq!(split(&["tool", "--", "--"]), (os(&["tool"]), os(&["--"])));
+        assert_eq!(split(&["-f", "tool", "a", "--"]).1, os(&["a", "--"]));
+    }
+
+    #[test]
+    fn cargo_shorthand_names_the_subcommand_crate() {
+        let mut cli = Cli::try_parse_from(["cargox", "cargo"]).unwrap();
+        cli.args = vec!["deny@0.14".into(), "check".into()];
+        cli.expand_cargo_shorthand();
+        assert_eq!(cli.crate_spec.as_deref(), Some("cargo-deny@0.14"));
+        assert_eq!(cli.args, vec![OsString::from("check")]);
+
+        let mut cli = Cli::try_parse_from(["cargox", "cargo"]).unwrap();
+        cli.args = vec!["--version".into()];
+        cli.expand_cargo_shorthand();
+        assert_eq!(cli.crate_spec.as_deref(), Some("cargo"));
+
+        let mut cli = Cli::try_parse_from(["cargox", "cargo@0.80"]).unwrap();
+        cli.args = vec!["build".into()];
+        cli.expand_cargo_shorthand();
+        assert_eq!(cli.crate_spec.as_deref(), Some("cargo@0.80"));
+
+        for builtin in ["build", "test", "t", "clippy", "install@1"] {
+            let mut cli = Cli::try_parse_from(["cargox", "cargo"]).unwrap();
+            cli.args = vec![builtin.into(), "--release".into()];
+            cli.expand_cargo_shorthand();
+            assert_eq!(cli.crate_spec.as_deref(), Some("cargo"), "{builtin}");
+            assert_eq!(cli.args.len(), 2);
+        }
+    }
+
+    #[test]
+    fn is_cargo_x_checks_the_program_name() {
+        assert!(is_cargo_x(OsStr::new("/home/me/.cargo/bin/cargo-x")));
+        assert!(is_cargo_x(OsStr::new("cargo-x.exe")));
+        assert!(!is_cargo_x(OsStr::new("/usr/local/bin/cargox")));
+        assert!(!is_cargo_x(OsStr::new("cargo-xtask")));
+    }
+
     #[test]
     fn parse_args_handles_force_flag() {
         let cli = Cli::try_parse_from(["cargox", "-f", "mycrate"]).unwrap();
-        assert_eq!(cli.crate_spec, "mycrate");
+        assert_eq!(cli.crate_spec.as_deref(), Some("mycrate"));
         assert!(cli.force);
     }
+
+    #[test]
+    fn parse_args_handles_toolchain_flag() {
+        let cli = Cli::try_parse_from(["cargox", "--toolchain", "nightly", "mycrate"]).unwrap();
+        assert_eq!(cli.toolchain.as_deref(), Some("nightly"));
+        assert_eq!(cli.crate_spec.as_deref(), Some("mycrate"));
+    }
+
+    #[test]
+    fn parse_args_handles_path_flag() {
+        let cli = Cli::try_parse_from(["cargox", "--path", "tools/codegen"]).unwrap();
+        assert_eq!(cli.path, Some(PathBuf::from("tools/codegen")));
+        assert_eq!(cli.crate_spec, None);
+
+        assert!(Cli::try_parse_from(["cargox", "--force"]).is_err());
+    }
+
+    #[test]
+    fn parse_args_handles_cache_flag() {
+        let cli = Cli::try_parse_from(["cargox", "--cache", "prune"]).unwrap();
+        assert_eq!(cli.cache, Some(CacheAction::Prune));
+        assert_eq!(cli.crate_spec, None);
+
+        assert!(Cli::try_parse_from(["cargox", "--cache", "info", "mycrate"]).is_err());
+    }
+
+    #[test]
+    fn parse_args_handles_target_flag() {
+        let cli = Cli::try_parse_from(["cargox", "--target", "aarch64-unknown-linux-gnu", "bat"])
+            .unwrap();
+        assert_eq!(cli.target.as_deref(), Some("aarch64-unknown-linux-gnu"));
+        assert!(!cli.allow_foreign);
+
+        assert!(Cli::try_parse_from(["cargox", "--allow-foreign", "bat"]).is_err());
+        assert!(Cli::try_parse_from(["cargox", "--list"]).unwrap().list);
+
+        let cli = Cli::try_parse_from(["cargox", "--as-of", "2024-03-01", "bat"]).unwrap();
+        assert_eq!(cli.as_of, Some(1_709_251_200));
+        assert!(Cli::try_parse_from(["cargox", "--as-of", "last week", "bat"]).is_err());
+    }
 }
@@ -0,0 +1,393 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::paths::get_config_path;
+
+/// Cache size used when the build cache is enabled without an explicit `max-size`.
+const DEFAULT_BUILD_CACHE_SIZE: u64 = 10 * 1024 * 1024 * 1024;
+
+/// Settings read from cargox's own `config.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub build_cache: BuildCacheConfig,
+    pub install: InstallConfig,
+    pub smoke_test: SmokeTestConfig,
+    pub execute: ExecuteConfig,
+    pub trust: TrustConfig,
+    pub advisories: AdvisoriesConfig,
+    pub lock: LockRules,
+}
+
+/// A `cargox.toml` in the current directory or one of its ancestors, holding
+/// project-wide settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub trust: TrustConfig,
+    pub lock: LockRules,
+}
+
+/// `[build-cache]`: reuse compiled dependencies across source installs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BuildCacheConfig {
+    /// Keep a persistent target directory per toolchain instead of a fresh temp dir
+    pub enabled: bool,
+    /// Size limit such as `10G` or `500MiB`; least recently used entries are evicted
+    pub max_size: Option<String>,
+    /// Compiler wrapper such as `sccache`, passed to cargo as `RUSTC_WRAPPER`
+    pub rustc_wrapper: Option<PathBuf>,
+}
+
+/// `[install]`: how prebuilt binaries are chosen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct InstallConfig {
+    /// On Linux, ask `cargo-binstall` for `*-unknown-linux-musl` artifacts first and
+    /// fall back to the gnu ones
+    pub prefer_musl: bool,
+    /// Quarantine for new releases such as `3d` or `12h`: newer versions are skipped
+    /// unless pinned exactly with `=`
+    pub min_release_age: Option<String>,
+}
+
+/// `[smoke-test]`: run freshly installed binaries once before trusting them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SmokeTestConfig {
+    pub enabled: bool,
+    /// How long a probe may run before the install is considered broken (default 10)
+    pub timeout_secs: Option<u64>,
+    /// Per-crate probes replacing the default `--version` check, keyed by crate name
+    pub probes: BTreeMap<String, SmokeProbe>,
+}
+
+/// `[smoke-test.probes.<crate>]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SmokeProbe {
+    pub args: Vec<String>,
+    /// Require the installed version to appear in the probe's output
+    #[serde(default)]
+    pub check_version: bool,
+}
+
+/// `[execute]`: checks made before running a stored binary.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ExecuteConfig {
+    /// Compare the binary with the SHA-256 recorded at install time
+    pub verify_checksum: bool,
+    /// Refuse to run an installed version that has been yanked since, instead of
+    /// warning about it
+    pub refuse_yanked: bool,
+}
+
+/// `[advisories]`: check crate versions against a RustSec advisory database.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AdvisoriesConfig {
+    /// Checkout of the RustSec advisory-db; defaults to `$CARGO_HOME/advisory-db`,
+    /// where `cargo audit` keeps it
+    pub db: Option<PathBuf>,
+    /// Refuse to install or run versions with a vulnerability advisory instead of
+    /// warning about them
+    pub strict: bool,
+}
+
+/// `[trust]`: crates that may be installed without asking, and crates that may never
+/// be run. Entries are crate names, optionally ending in `*` to match a prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TrustConfig {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// `[lock]`: tools that must match a library in the project's `Cargo.lock`, keyed by
+/// the tool's crate name, e.g. `wasm-bindgen-cli = "wasm-bindgen"`.
+pub type LockRules = BTreeMap<String, String>;
+
+impl Config {
+    /// Load the config file if it exists. `CARGOX_BUILD_CACHE`, `CARGOX_PREFER_MUSL`,
+    /// `CARGOX_SMOKE_TEST` and `CARGOX_VERIFY_CHECKSUM` (`1` or `0`) override
+    /// `build-cache.enabled`, `install.prefer-musl`, `smoke-test.enabled` and
+    /// `execute.verify-checksum`; `CARGOX_MIN_RELEASE_AGE` and `CARGOX_ADVISORY_DB`
+    /// override `install.min-release-age` and `advisories.db`.
+    pub fn load() -> Result<Self> {
+        let mut config = match get_config_path() {
+            Some(path) if path.is_file() => {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                Self::parse(&contents)
+                    .with_context(|| format!("failed to parse {}", path.display()))?
+            }
+            _ => Self::default(),
+        };
+
+        if let Some(enabled) = env_flag("CARGOX_BUILD_CACHE") {
+            config.build_cache.enabled = enabled;
+        }
+        if let Some(prefer_musl) = env_flag("CARGOX_PREFER_MUSL") {
+            config.install.prefer_musl = prefer_musl;
+        }
+        if let Some(enabled) = env_flag("CARGOX_SMOKE_TEST") {
+            config.smoke_test.enabled = enabled;
+        }
+        if let Some(verify) = env_flag("CARGOX_VERIFY_CHECKSUM") {
+            config.execute.verify_checksum = verify;
+        }
+        if let Some(db) = env::var_os("CARGOX_ADVISORY_DB") {
+            config.advisories.db = Some(PathBuf::from(db));
+        }
+        if let Some(age) = env::var_os("CARGOX_MIN_RELEASE_AGE") {
+            config.install.min_release_age = Some(age.to_string_lossy().into_owned());
+        }
+
+        Ok(config)
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        // Surface a bad size or age when the file is read rather than after a build
+        config.build_cache.max_bytes()?;
+        config.install.min_release_age()?;
+        Ok(config)
+    }
+}
+
+impl ProjectConfig {
+    pub const FILE_NAME: &str = "cargox.toml";
+
+    /// Load the nearest `cargox.toml` at or above `dir`, returning its path too. A file
+    /// that `CARGOX_CONFIG` already names as the global config is not a project file.
+    pub fn find(dir: &Path) -> Result<Option<(PathBuf, Self)>> {
+        let global = get_config_path().and_then(|path| path.canonicalize().ok());
+        for ancestor in dir.ancestors() {
+            let path = ancestor.join(Self::FILE_NAME);
+            if !path.is_file() || path.canonicalize().ok() == global {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let config = toml::from_str(&contents)
+                .with_context(|| format!("failed to parse {}", path.display()))?;
+            return Ok(Some((path, config)));
+        }
+        Ok(None)
+    }
+}
+
+impl BuildCacheConfig {
+    pub fn max_bytes(&self) -> Result<u64> {
+        match &self.max_size {
+            Some(size) => parse_size(size),
+            None => Ok(DEFAULT_BUILD_CACHE_SIZE),
+        }
+    }
+}
+
+impl InstallConfig {
+    /// `min-release-age` in seconds; `None` when unset.
+    pub fn min_release_age(&self) -> Result<Option<u64>> {
+        self.min_release_age
+            .as_deref()
+            .map(parse_duration)
+            .transpose()
+    }
+}
+
+/// Read a boolean environment variable; unset means `None`, and `0`, `false`, `no`
+/// or `off` are false.
+pub fn env_flag(name: &str) -> Option<bool> {
+    let value = env::var_os(name)?;
+    Some(!matches!(
+        value.to_string_lossy().trim(),
+        "" | "0" | "false" | "no" | "off"
+    ))
+}
+
+/// Parse a human readable size. `K`, `M`, `G` and `T` (optionally followed by `B` or
+/// `iB`) are all binary multiples, matching what `du -h` reports.
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let number: u64 = digits
+        .parse()
+        .map_err(|_| anyhow!("invalid size `{value}`"))?;
+
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let shift = match unit {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(anyhow!("invalid size `{value}`: unknown unit")),
+    };
+
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| anyhow!("size `{value}` is too large"))
+}
+
+/// Parse a duration such as `3d`, `12h`, `30m`, `2w` or `90s` into seconds. A bare
+/// number is a count of seconds.
+pub fn parse_duration(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let number: u64 = digits
+        .parse()
+        .map_err(|_| anyhow!("invalid duration `{value}`"))?;
+
+    let seconds = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(anyhow!(
+                "invalid duration `{value}`: expected a unit of s, m, h, d or w"
+            ));
+        }
+    };
+
+    number
+        .checked_mul(seconds)
+        .ok_or_else(|| anyhow!("duration `{value}` is too large"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::env_lock;
+
+    #[test]
+    fn parse_size_accepts_common_units() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("4K").unwrap(), 4096);
+        assert_eq!(parse_size("500M").unwrap(), 500 << 20);
+        assert_eq!(parse_size("10GiB").unwrap(), 10 << 30);
+        assert_eq!(parse_size("2 gb").unwrap(), 2 << 30);
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("10X").is_err());
+    }
+
+    #[test]
+    fn parse_duration_accepts_common_units() {
+        assert_eq!(parse_duration("90").unwrap(), 90);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("12h").unwrap(), 12 * 3600);
+        assert_eq!(parse_duration("3d").unwrap(), 3 * 86400);
+        assert_eq!(parse_duration("2w").unwrap(), 14 * 86400);
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3 days").is_err());
+        assert!(Config::parse("[install]\nmin-release-age = \"soon\"\n").is_err());
+        assert_eq!(
+            Config::parse("[install]\nmin-release-age = \"3d\"\n")
+                .unwrap()
+                .install
+                .min_release_age()
+                .unwrap(),
+            Some(3 * 86400)
+        );
+    }
+
+    #[test]
+    fn parse_reads_config_tables() {
+        let config = Config::parse(
+            r#"
+[build-cache]
+enabled = true
+max-size = "2G"
+rustc-wrapper = "sccache"
+"#,
+        )
+        .unwrap();
+        assert!(config.build_cache.enabled);
+        assert!(!config.install.prefer_musl);
+        assert_eq!(config.build_cache.max_bytes().unwrap(), 2 << 30);
+        assert_eq!(
+            config.build_cache.rustc_wrapper,
+            Some(PathBuf::from("sccache"))
+        );
+
+        let default = Config::parse("").unwrap();
+        assert!(!default.build_cache.enabled);
+        assert_eq!(
+            default.build_cache.max_bytes().unwrap(),
+            DEFAULT_BUILD_CACHE_SIZE
+        );
+
+        assert!(Config::parse("[build-cache]\nmax-size = \"lots\"\n").is_err());
+        assert!(Config::parse("[build-cache]\nenable = true\n").is_err());
+        assert!(
+            Config::parse("[install]\nprefer-musl = true\n")
+                .unwrap()
+                .install
+                .prefer_musl
+        );
+
+        let smoke = Config::parse(
+            r#"
+[smoke-test]
+enabled = true
+timeout-secs = 3
+
+[smoke-test.probes.taplo-cli]
+args = ["help"]
+"#,
+        )
+        .unwrap()
+        .smoke_test;
+        assert!(smoke.enabled);
+        assert_eq!(smoke.timeout_secs, Some(3));
+        assert_eq!(smoke.probes["taplo-cli"].args, vec!["help"]);
+        assert!(!smoke.probes["taplo-cli"].check_version);
+
+        let trust = Config::parse("[trust]\nallow = [\"ripgrep\", \"cargo-*\"]\n")
+            .unwrap()
+            .trust;
+        assert_eq!(trust.allow, vec!["ripgrep", "cargo-*"]);
+        assert!(trust.deny.is_empty());
+
+        let lock = Config::parse("[lock]\nwasm-bindgen-cli = \"wasm-bindgen\"\n")
+            .unwrap()
+            .lock;
+        assert_eq!(lock["wasm-bindgen-cli"], "wasm-bindgen");
+    }
+
+    #[test]
+    fn project_config_is_found_in_ancestors() {
+        let _guard = env_lock().lock().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let nested = temp.path().join("crates").join("tool");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(ProjectConfig::find(&nested).unwrap(), None);
+
+        let path = temp.path().join(ProjectConfig::FILE_NAME);
+        fs::write(&path, "[trust]\ndeny = [\"evil\"]\n").unwrap();
+        let (found, config) = ProjectConfig::find(&nested).unwrap().unwrap();
+        assert_eq!(found, path);
+        assert_eq!(config.trust.deny, vec!["evil"]);
+
+        fs::write(&path, "[trust]\ndenied = []\n").unwrap();
+        assert!(ProjectConfig::find(&nested).is_err());
+    }
+}
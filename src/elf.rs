@@ -0,0 +1,659 @@
+use anyhow::{Context, Result, anyhow};
+use semver::Version;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+const DT_VERNEED: u64 = 0x6fff_fffe;
+const DT_VERNEEDNUM: u64 = 0x6fff_ffff;
+
+/// musl's message for errno 0; glibc says `Success`.
+const MUSL_STRERROR: &[u8] = b"\0No error information\0";
+
+/// The parts of an ELF executable that decide whether it can run on a host.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElfInfo {
+    /// `e_machine`, e.g. 62 for x86-64
+    pub machine: u16,
+    pub is_64: bool,
+    /// Program interpreter (dynamic loader); `None` for static executables
+    pub interpreter: Option<String>,
+    /// Shared libraries from `DT_NEEDED`
+    pub needed: Vec<String>,
+    /// `DT_RUNPATH`/`DT_RPATH` search directories, possibly containing `$ORIGIN`
+    pub runpath: Vec<String>,
+    /// Symbol versions required from shared libraries, e.g. `GLIBC_2.34`
+    pub required_versions: BTreeSet<String>,
+    /// Whether the file carries musl's `strerror` table, the tell of a static musl
+    /// binary, which has no loader to name its libc
+    pub musl_strings: bool,
+}
+
+impl ElfInfo {
+    /// The C library the binary was linked against, judged by its loader.
+    pub fn libc(&self) -> &'static str {
+        match self.interpreter.as_deref() {
+            None if self.musl_strings => "musl",
+            None => "static",
+            Some(interp) if interp.contains("ld-musl") => "musl",
+            Some(_) => "gnu",
+        }
+    }
+
+    /// The newest `GLIBC_x.y` symbol version the binary requires.
+    pub fn required_glibc(&self) -> Option<Version> {
+        self.required_versions
+            .iter()
+            .filter_map(|name| name.strip_prefix("GLIBC_"))
+            .filter_map(parse_glibc_version)
+            .max()
+    }
+}
+
+/// What the running machine offers to dynamically linked executables.
+#[derive(Debug, Clone, Default)]
+pub struct Host {
+    /// `(e_machine, is_64)` of native executables, when known
+    pub machine: Option<(u16, bool)>,
+    pub glibc: Option<Version>,
+    pub library_dirs: Vec<PathBuf>,
+}
+
+impl Host {
+    pub fn detect() -> Self {
+        Self {
+            machine: native_machine(),
+            glibc: host_glibc(),
+            library_dirs: library_dirs(),
+        }
+    }
+}
+
+/// Inspect `path`, returning `None` when it is not an ELF file (e.g. on macOS or Windows).
+pub fn inspect(path: &Path) -> Result<Option<ElfInfo>> {
+    let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse(&data).with_context(|| format!("failed to parse ELF file {}", path.display()))
+}
+
+/// Reasons the executable at `path` cannot run on `host`; empty when it looks fine.
+pub fn incompatibilities(info: &ElfInfo, path: &Path, host: &Host) -> Vec<String> {
+    if let Some((machine, is_64)) = host.machine
+        && (info.machine, info.is_64) != (machine, is_64)
+    {
+        return vec![format!(
+            "it is built for {} but this host is {}",
+            machine_name(info.machine, info.is_64),
+            env::consts::ARCH
+        )];
+    }
+
+    let mut problems = Vec::new();
+    if let Some(interpreter) = &info.interpreter
+        && !Path::new(interpreter).exists()
+    {
+        problems.push(format!(
+            "its dynamic loader {interpreter} does not exist (it is linked against {} libc)",
+            info.libc()
+        ));
+    }
+
+    let origin = path.parent().unwrap_or(Path::new("/"));
+    let runpath = info
+        .runpath
+        .iter()
+        .map(|dir| PathBuf::from(dir.replace("$ORIGIN", &origin.to_string_lossy())));
+    let search: Vec<PathBuf> = runpath.chain(host.library_dirs.iter().cloned()).collect();
+    for library in &info.needed {
+        if !search.iter().any(|dir| dir.join(library).exists()) {
+            problems.push(format!("shared library {library} was not found"));
+        }
+    }
+
+    if let (Some(required), Some(available)) = (info.required_glibc(), &host.glibc)
+        && required > *available
+    {
+        problems.push(format!(
+            "it requires GLIBC_{}.{} but this host has glibc {}.{}",
+            required.major, required.minor, available.major, available.minor
+        ));
+    }
+
+    problems
+}
+
+fn parse(data: &[u8]) -> Result<Option<ElfInfo>> {
+    if data.len() < 16 || &data[..4] != b"\x7fELF" {
+        return Ok(None);
+    }
+    let reader = Reader {
+        data,
+        is_64: match data[4] {
+            1 => false,
+            2 => true,
+            class => return Err(anyhow!("unknown ELF class {class}")),
+        },
+        little_endian: match data[5] {
+            1 => true,
+            2 => false,
+            encoding => return Err(anyhow!("unknown ELF data encoding {encoding}")),
+        },
+    };
+
+    let mut info = ElfInfo {
+        machine: reader.u16(0x12)?,
+        is_64: reader.is_64,
+        ..ElfInfo::default()
+    };
+
+    let (phoff, phentsize, phnum) = if reader.is_64 {
+        (reader.u64(0x20)?, reader.u16(0x36)?, reader.u16(0x38)?)
+    } else {
+        (
+            u64::from(reader.u32(0x1c)?),
+            reader.u16(0x2a)?,
+            reader.u16(0x2c)?,
+        )
+    };
+
+    let mut segments = Vec::new();
+    for index in 0..u64::from(phnum) {
+        segments.push(reader.segment(phoff + index * u64::from(phentsize))?);
+    }
+
+    for segment in &segments {
+        if segment.kind == PT_INTERP {
+            info.interpreter = Some(reader.cstr(segment.offset, segment.offset + segment.size)?);
+        }
+    }
+
+    if let Some(dynamic) = segments.iter().find(|segment| segment.kind == PT_DYNAMIC) {
+        read_dynamic(&reader, dynamic, &segments, &mut info)?;
+    }
+    if info.interpreter.is_none() {
+        info.musl_strings = data
+            .windows(MUSL_STRERROR.len())
+            .any(|window| window == MUSL_STRERROR);
+    }
+
+    Ok(Some(info))
+}
+
+/// Read `DT_NEEDED`, run paths and version requirements from the dynamic segment.
+fn read_dynamic(
+    reader: &Reader,
+    dynamic: &Segment,
+    segments: &[Segment],
+    info: &mut ElfInfo,
+) -> Result<()> {
+    let entry_size = if reader.is_64 { 16 } else { 8 };
+    let mut entries = Vec::new();
+    let mut offset = dynamic.offset;
+    while offset + entry_size <= dynamic.offset + dynamic.size {
+        let (tag, value) = if reader.is_64 {
+            (reader.u64(offset)?, reader.u64(offset + 8)?)
+        } else {
+            (
+                u64::from(reader.u32(offset)?),
+                u64::from(reader.u32(offset + 4)?),
+            )
+        };
+        if tag == DT_NULL {
+            break;
+        }
+        entries.push((tag, value));
+        offset += entry_size;
+    }
+
+    let find = |wanted: u64| {
+        entries
+            .iter()
+            .find(|(tag, _)| *tag == wanted)
+            .map(|(_, value)| *value)
+    };
+    let Some(strtab) = find(DT_STRTAB).and_then(|addr| file_offset(segments, addr)) else {
+        return Ok(());
+    };
+    let string = |index: u64| reader.cstr(strtab + index, reader.data.len() as u64);
+
+    for (tag, value) in &entries {
+        match *tag {
+            DT_NEEDED => info.needed.push(string(*value)?),
+            DT_RUNPATH | DT_RPATH => info
+                .runpath
+                .extend(string(*value)?.split(':').map(str::to_owned)),
+            _ => {}
+        }
+    }
+
+    let (Some(verneed), Some(count)) = (
+        find(DT_VERNEED).and_then(|addr| file_offset(segments, addr)),
+        find(DT_VERNEEDNUM),
+    ) else {
+        return Ok(());
+    };
+    let mut need = verneed;
+    for _ in 0..count {
+        let aux_count = reader.u16(need + 2)?;
+        let mut aux = need + u64::from(reader.u32(need + 8)?);
+        for _ in 0..aux_count {
+            info.required_versions
+                .insert(string(u64::from(reader.u32(aux + 8)?))?);
+            aux += u64::from(reader.u32(aux + 12)?);
+        }
+        let next = reader.u32(need + 12)?;
+        if next == 0 {
+            break;
+        }
+        need += u64::from(next);
+    }
+
+    Ok(())
+}
+
+/// Map a virtual address to a file offset through the loadable segments.
+fn file_offset(segments: &[Segment], addr: u64) -> Option<u64> {
+    segments
+        .iter()
+        .filter(|segment| segment.kind == PT_LOAD)
+        .find(|segment| addr >= segment.vaddr && addr < segment.vaddr + segment.size)
+        .map(|segment| addr - segment.vaddr + segment.offset)
+}
+
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    size: u64,
+}
+
+/// Bounds-checked reads of ELF fields in the file's byte order.
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn slice(&self, offset: u64, len: u64) -> Result<&[u8]> {
+        let start = usize::try_from(offset)?;
+        let end = start
+            .checked_add(usize::try_from(len)?)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| anyhow!("ELF file is truncated"))?;
+        Ok(&self.data[start..end])
+    }
+
+    /// A NUL-terminated string starting at `offset` and ending before `limit`.
+    fn cstr(&self, offset: u64, limit: u64) -> Result<String> {
+        let limit = limit.min(self.data.len() as u64);
+        let bytes = self.slice(offset, limit.saturating_sub(offset))?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+
+    fn segment(&self, header: u64) -> Result<Segment> {
+        Ok(if self.is_64 {
+            Segment {
+                kind: self.u32(header)?,
+                offset: self.u64(header + 0x08)?,
+                vaddr: self.u64(header + 0x10)?,
+                size: self.u64(header + 0x20)?,
+            }
+        } else {
+            Segment {
+                kind: self.u32(header)?,
+                offset: u64::from(self.u32(header + 0x04)?),
+                vaddr: u64::from(self.u32(header + 0x08)?),
+                size: u64::from(self.u32(header + 0x10)?),
+            }
+        })
+    }
+
+    fn bytes<const N: usize>(&self, offset: u64) -> Result<[u8; N]> {
+        Ok(self.slice(offset, N as u64)?.try_into()?)
+    }
+
+    fn u16(&self, offset: u64) -> Result<u16> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: u64) -> Result<u32> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: u64) -> Result<u64> {
+        let bytes = self.bytes(offset)?;
+        Ok(if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    }
+}
+
+fn parse_glibc_version(value: &str) -> Option<Version> {
+    let mut parts = value.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    Some(Version::new(major, minor, patch))
+}
+
+fn native_machine() -> Option<(u16, bool)> {
+    Some(match env::consts::ARCH {
+        "x86" => (3, false),
+        "x86_64" => (62, true),
+        "arm" => (40, false),
+        "aarch64" => (183, true),
+        "riscv64" => (243, true),
+        "powerpc64" => (21, true),
+        "s390x" => (22, true),
+        "loongarch64" => (258, true),
+        _ => return None,
+    })
+}
+
+fn machine_name(machine: u16, is_64: bool) -> String {
+    let name = match (machine, is_64) {
+        (3, _) => "x86",
+        (62, true) => "x86_64",
+        (40, _) => "arm",
+        (183, _) => "aarch64",
+        (243, true) => "riscv64",
+        (21, true) => "powerpc64",
+        (22, true) => "s390x",
+        (258, _) => "loongarch64",
+        _ => return format!("ELF machine {machine}"),
+    };
+    name.to_owned()
+}
+
+/// `getconf GNU_LIBC_VERSION` prints e.g. `glibc 2.39`; it fails on non-glibc systems.
+fn host_glibc() -> Option<Version> {
+    let output = Command::new("getconf")
+        .arg("GNU_LIBC_VERSION")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_glibc_version(stdout.trim().strip_prefix("glibc ")?)
+}
+
+/// Directories the dynamic loader searches, approximating `ld.so` and musl's loader.
+fn library_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("LD_LIBRARY_PATH")
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default();
+
+    read_ld_so_conf(Path::new("/etc/ld.so.conf"), &mut dirs, 0);
+    if let Ok(contents) = fs::read_to_string(format!("/etc/ld-musl-{}.path", env::consts::ARCH)) {
+        dirs.extend(
+            contents
+                .split([':', '\n'])
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        );
+    }
+
+    let multiarch = format!("{}-linux-gnu", env::consts::ARCH);
+    for dir in ["/lib", "/lib64", "/usr/lib", "/usr/lib64", "/usr/local/lib"] {
+        dirs.push(PathBuf::from(dir));
+        dirs.push(Path::new(dir).join(&multiarch));
+    }
+    dirs
+}
+
+fn read_ld_so_conf(path: &Path, dirs: &mut Vec<PathBuf>, depth: usize) {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return;
+    };
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(pattern) = line.strip_prefix("include ") {
+            // Only the `dir/*.conf` form used by distributions is supported
+            let pattern = Path::new(pattern.trim());
+            let (Some(dir), Some(suffix)) = (
+                pattern.parent(),
+                pattern
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_prefix('*')),
+            ) else {
+                continue;
+            };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut includes: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.to_string_lossy().ends_with(suffix))
+                .collect();
+            includes.sort();
+            if depth < 4 {
+                for include in includes {
+                    read_ld_so_conf(&include, dirs, depth + 1);
+                }
+            }
+        } else if !line.is_empty() {
+            dirs.push(PathBuf::from(line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A synthetic little-endian ELF64 executable: one PT_LOAD mapping the whole file
+    /// at address 0, a dynamic section, and optionally a PT_INTERP segment.
+    fn elf64(interpreter: Option<&str>, needed: &[&str], glibc: &[&str]) -> Vec<u8> {
+        let phnum = if interpreter.is_some() { 3 } else { 2 };
+        let mut data = vec![0u8; 0x40 + phnum * 0x38];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2;
+        data[5] = 1;
+        data[0x12..0x14].copy_from_slice(&62u16.to_le_bytes());
+        data[0x20..0x28].copy_from_slice(&0x40u64.to_le_bytes());
+        data[0x36..0x38].copy_from_slice(&0x38u16.to_le_bytes());
+        data[0x38..0x3a].copy_from_slice(&(phnum as u16).to_le_bytes());
+
+        let mut strtab = vec![0u8];
+        let mut add = |s: &str| {
+            let offset = strtab.len() as u64;
+            strtab.extend(s.as_bytes());
+            strtab.push(0);
+            offset
+        };
+        let needed: Vec<u64> = needed.iter().map(|lib| add(lib)).collect();
+        let libc = add("libc.so.6");
+        let versions: Vec<u64> = glibc.iter().map(|v| add(&format!("GLIBC_{v}"))).collect();
+
+        let interp_offset = data.len() as u64;
+        if let Some(interpreter) = interpreter {
+            data.extend(interpreter.as_bytes());
+            data.push(0);
+        }
+        let strtab_offset = data.len() as u64;
+        data.extend(&strtab);
+        while !data.len().is_multiple_of(8) {
+            data.push(0);
+        }
+
+        let verneed_offset = data.len() as u64;
+        if !versions.is_empty() {
+            data.extend(1u16.to_le_bytes());
+            data.extend((versions.len() as u16).to_le_bytes());
+            data.extend((libc as u32).to_le_bytes());
+            data.extend(16u32.to_le_bytes());
+            data.extend(0u32.to_le_bytes());
+            for (index, name) in versions.iter().enumerate() {
+                let next = if index + 1 < versions.len() { 16u32 } else { 0 };
+                data.extend(0u32.to_le_bytes());
+                data.extend(0u16.to_le_bytes());
+                data.extend(0u16.to_le_bytes());
+                data.extend((*name as u32).to_le_bytes());
+                data.extend(next.to_le_bytes());
+            }
+        }
+
+        let dynamic_offset = data.len() as u64;
+        let mut entries: Vec<(u64, u64)> = needed.iter().map(|n| (DT_NEEDED, *n)).collect();
+        entries.push((DT_STRTAB, strtab_offset));
+        if !versions.is_empty() {
+            entries.push((DT_VERNEED, verneed_offset));
+            entries.push((DT_VERNEEDNUM, 1));
+        }
+        entries.push((DT_NULL, 0));
+        for (tag, value) in &entries {
+            data.extend(tag.to_le_bytes());
+            data.extend(value.to_le_bytes());
+        }
+        let dynamic_size = data.len() as u64 - dynamic_offset;
+
+        let mut headers = vec![
+            (PT_LOAD, 0, data.len() as u64),
+            (PT_DYNAMIC, dynamic_offset, dynamic_size),
+        ];
+        if let Some(interpreter) = interpreter {
+            headers.push((PT_INTERP, interp_offset, interpreter.len() as u64 + 1));
+        }
+        for (index, (kind, offset, size)) in headers.into_iter().enumerate() {
+            let header = 0x40 + index * 0x38;
+            data[header..header + 4].copy_from_slice(&kind.to_le_bytes());
+            data[header + 0x08..header + 0x10].copy_from_slice(&offset.to_le_bytes());
+            data[header + 0x10..header + 0x18].copy_from_slice(&offset.to_le_bytes());
+            data[header + 0x20..header + 0x28].copy_from_slice(&size.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn parse_detects_libc_from_interpreter() {
+        let gnu = parse(&elf64(Some("/lib64/ld-linux-x86-64.so.2"), &[], &[]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            gnu.interpreter.as_deref(),
+            Some("/lib64/ld-linux-x86-64.so.2")
+        );
+        assert_eq!(gnu.libc(), "gnu");
+
+        let musl = parse(&elf64(Some("/lib/ld-musl-x86_64.so.1"), &[], &[]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(musl.libc(), "musl");
+
+        assert_eq!(
+            parse(&elf64(None, &[], &[])).unwrap().unwrap().libc(),
+            "static"
+        );
+
+        let mut static_musl = elf64(None, &[], &[]);
+        static_musl.extend(b"\0No error information\0Illegal byte sequence\0");
+        assert_eq!(parse(&static_musl).unwrap().unwrap().libc(), "musl");
+    }
+
+    #[test]
+    fn parse_reads_needed_libraries_and_glibc_versions() {
+        let info = parse(&elf64(
+            Some("/lib64/ld-linux-x86-64.so.2"),
+            &["libssl.so.3", "libc.so.6"],
+            &["2.17", "2.34", "2.2.5"],
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(info.machine, 62);
+        assert!(info.is_64);
+        assert_eq!(info.needed, vec!["libssl.so.3", "libc.so.6"]);
+        assert!(info.required_versions.contains("GLIBC_2.2.5"));
+        assert_eq!(info.required_glibc(), Some(Version::new(2, 34, 0)));
+    }
+
+    #[test]
+    fn parse_ignores_non_elf_and_rejects_truncated_files() {
+        assert_eq!(parse(b"#!/bin/sh\necho hi\n").unwrap(), None);
+
+        let mut truncated = elf64(Some("/lib/ld-musl-x86_64.so.1"), &[], &[]);
+        truncated.truncate(0x50);
+        assert!(parse(&truncated).is_err());
+    }
+
+    #[test]
+    fn incompatibilities_explain_what_is_missing() {
+        let libs = tempfile::tempdir().unwrap();
+        fs::write(libs.path().join("libc.so.6"), "").unwrap();
+        let loader = libs.path().join("ld.so");
+        fs::write(&loader, "").unwrap();
+        let host = Host {
+            machine: Some((62, true)),
+            glibc: Some(Version::new(2, 31, 0)),
+            library_dirs: vec![libs.path().to_path_buf()],
+        };
+        let path = Path::new("/store/bin/tool");
+
+        let ok = parse(&elf64(
+            Some(&loader.to_string_lossy()),
+            &["libc.so.6"],
+            &["2.17"],
+        ))
+        .unwrap()
+        .unwrap();
+        assert!(incompatibilities(&ok, path, &host).is_empty());
+
+        let broken = parse(&elf64(
+            Some("/lib/ld-musl-x86_64.so.1"),
+            &["libssl.so.3", "libc.so.6"],
+            &["2.39"],
+        ))
+        .unwrap()
+        .unwrap();
+        let problems = incompatibilities(&broken, path, &host);
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(problems[0].contains("ld-musl"));
+        assert!(problems[1].contains("libssl.so.3"));
+        assert!(problems[2].contains("GLIBC_2.39"));
+
+        let foreign = ElfInfo { machine: 183, ..ok };
+        let problems = incompatibilities(&foreign, path, &host);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("aarch64"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn running_executable_is_compatible_with_this_host() {
+        let exe = std::env::current_exe().unwrap();
+        let info = inspect(&exe).unwrap().unwrap();
+        if info.libc() == "gnu" {
+            assert!(info.needed.iter().any(|lib| lib.starts_with("libc.so")));
+            assert!(info.required_glibc().is_some());
+        }
+        assert_eq!(
+            incompatibilities(&info, &exe, &Host::detect()),
+            Vec::<String>::new()
+        );
+    }
+}
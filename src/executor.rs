@@ -1,20 +1,154 @@
+use crate::manifest::{InstallRecord, file_sha256, lookup};
 use crate::paths::get_install_dir;
 use anyhow::{Context, Result, anyhow};
+use std::env;
 use std::ffi::OsString;
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus};
 
-pub fn execute_binary(binary_path: &Path, args: &[OsString]) -> Result<ExitStatus> {
+/// The arguments and extra environment a stored binary is started with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub args: Vec<OsString>,
+    pub env: Vec<(&'static str, OsString)>,
+}
+
+impl Invocation {
+    /// Start `binary` with `args` as given, unless it is a cargo subcommand: cargo runs
+    /// `cargo deny check` as `cargo-deny deny check` with `CARGO` naming itself, and
+    /// `cargo-*` binaries are started the same way. The name is always inserted, as cargo
+    /// does, so arguments that happen to repeat it still reach the binary.
+    pub fn new(binary: &str, args: &[OsString]) -> Self {
+        let Some(subcommand) = binary
+            .strip_prefix("cargo-")
+            .filter(|name| !name.is_empty())
+        else {
+            return Self {
+                args: args.to_vec(),
+                env: Vec::new(),
+            };
+        };
+
+        let mut full_args = Vec::with_capacity(args.len() + 1);
+        full_args.push(OsString::from(subcommand));
+        full_args.extend_from_slice(args);
+        Self {
+            args: full_args,
+            env: cargo_path()
+                .map(|cargo| ("CARGO", cargo.into_os_string()))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+/// The cargo that would run the subcommand: `$CARGO` when cargox itself runs under
+/// cargo, otherwise the first `cargo` on `PATH`.
+fn cargo_path() -> Option<PathBuf> {
+    env::var_os("CARGO")
+        .map(PathBuf::from)
+        .or_else(|| which::which("cargo").ok())
+}
+
+/// Run the binary in place of cargox. On Unix the process image is replaced with
+/// `exec`, so the tool keeps cargox's PID, receives its signals alone and reports its
+/// own exit status; this only returns if that fails. Elsewhere the tool is spawned and
+/// waited for, with cargox ignoring the Ctrl+C that the console also delivers to it.
+pub fn execute_binary(binary_path: &Path, invocation: &Invocation) -> Result<ExitStatus> {
     ensure_within_install_dir(binary_path)?;
+    #[cfg(unix)]
+    ensure_secure_permissions(binary_path)?;
 
     let mut cmd = Command::new(binary_path);
-    cmd.args(args);
+    cmd.args(&invocation.args);
+    cmd.envs(invocation.env.iter().map(|(key, value)| (key, value)));
+
+    // Nothing written by cargox may be left behind in a buffer when the image is replaced
+    io::stdout()
+        .flush()
+        .context("failed to flush standard output")?;
+    run(cmd).with_context(|| format!("failed to execute {}", binary_path.display()))
+}
+
+#[cfg(unix)]
+fn run(mut cmd: Command) -> io::Result<ExitStatus> {
+    use std::os::unix::process::CommandExt;
+
+    Err(cmd.exec())
+}
+
+#[cfg(not(unix))]
+fn run(mut cmd: Command) -> io::Result<ExitStatus> {
+    #[cfg(windows)]
+    ignore_ctrl_c();
+    cmd.status()
+}
+
+/// Cargo does the same when it runs a subcommand: the tool decides what Ctrl+C means,
+/// and cargox waits to pass on its exit code.
+#[cfg(windows)]
+fn ignore_ctrl_c() {
+    use windows_sys::Win32::Foundation::{BOOL, TRUE};
+    use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+    unsafe extern "system" fn ignore(_ctrl_type: u32) -> BOOL {
+        TRUE
+    }
+    // SAFETY: the handler is a plain function that lives as long as the process
+    unsafe {
+        SetConsoleCtrlHandler(Some(ignore), TRUE);
+    }
+}
+
+/// The exit code to leave with for a tool's exit status. Like a shell, a tool killed by
+/// a signal is reported as 128 plus the signal number.
+pub fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    1
+}
+
+/// Compare a stored binary with the SHA-256 recorded when it was installed. Binaries
+/// without a recorded checksum are refused when `required`, and otherwise run with a note.
+pub fn verify_checksum(binary_path: &Path, required: bool) -> Result<()> {
+    let record = lookup(binary_path)?;
+    check_checksum(binary_path, record.as_ref(), required)
+}
 
-    let status = cmd
-        .status()
-        .with_context(|| format!("failed to execute {}", binary_path.display()))?;
+fn check_checksum(
+    binary_path: &Path,
+    record: Option<&InstallRecord>,
+    required: bool,
+) -> Result<()> {
+    let Some(expected) = record.and_then(|record| record.sha256.as_deref()) else {
+        if required {
+            return Err(anyhow!(
+                "no checksum is recorded for {}, refusing to run it",
+                binary_path.display()
+            ));
+        }
+        eprintln!(
+            "note: no checksum is recorded for {}; reinstall it to record one",
+            binary_path.display()
+        );
+        return Ok(());
+    };
 
-    Ok(status)
+    let actual = file_sha256(binary_path)?;
+    if actual != expected {
+        return Err(anyhow!(
+            "{} has been modified since it was installed (sha256 {actual}, expected {expected}), \
+             refusing to run it",
+            binary_path.display()
+        ));
+    }
+    Ok(())
 }
 
 fn ensure_within_install_dir(binary_path: &Path) -> Result<()> {
@@ -43,12 +177,108 @@ fn ensure_binary_within_dir(binary_path: &Path, install_dir: &Path) -> Result<()
     Ok(())
 }
 
+/// Refuse to run a binary that another user could have replaced: the binary and every
+/// directory above it must belong to us or root and must not be group or world
+/// writable (sticky directories such as `/tmp` excepted).
+#[cfg(unix)]
+fn ensure_secure_permissions(binary_path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let binary = binary_path
+        .canonicalize()
+        .with_context(|| format!("failed to canonicalize {}", binary_path.display()))?;
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let euid = unsafe { libc::geteuid() };
+
+    for path in binary.ancestors() {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("failed to inspect {}", path.display()))?;
+        if let Some(problem) = permission_problem(
+            path,
+            metadata.uid(),
+            metadata.mode(),
+            metadata.is_dir(),
+            euid,
+        ) {
+            return Err(anyhow!(
+                "refusing to execute {}: {problem}",
+                binary.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn permission_problem(path: &Path, uid: u32, mode: u32, is_dir: bool, euid: u32) -> Option<String> {
+    const STICKY: u32 = 0o1000;
+
+    if uid != euid && uid != 0 {
+        return Some(format!(
+            "{} is owned by uid {uid}, not by you (uid {euid}) or root; \
+             run `chown {euid} {}`",
+            path.display(),
+            path.display()
+        ));
+    }
+
+    let sticky_dir = is_dir && mode & STICKY != 0;
+    if mode & 0o022 != 0 && !sticky_dir {
+        let writers = match (mode & 0o020 != 0, mode & 0o002 != 0) {
+            (true, true) => "group and other users",
+            (true, false) => "its group",
+            _ => "other users",
+        };
+        return Some(format!(
+            "{} is writable by {writers} (mode {:o}); run `chmod go-w {}`",
+            path.display(),
+            mode & 0o7777,
+            path.display()
+        ));
+    }
+
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs;
     use tempfile::tempdir;
 
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn invocation_runs_cargo_subcommands_like_cargo() {
+        let plain = Invocation::new("bat", &args(&["--help"]));
+        assert_eq!(plain.args, args(&["--help"]));
+        assert!(plain.env.is_empty());
+
+        let deny = Invocation::new("cargo-deny", &args(&["check", "bans"]));
+        assert_eq!(deny.args, args(&["deny", "check", "bans"]));
+        let named = Invocation::new("cargo-deny", &args(&["deny", "check"]));
+        assert_eq!(named.args, args(&["deny", "deny", "check"]));
+        assert_eq!(
+            Invocation::new("cargo-nextest", &[]).args,
+            args(&["nextest"])
+        );
+        assert_eq!(Invocation::new("cargo-", &[]).args, args(&[]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_code_reports_signals_like_a_shell() {
+        use std::os::unix::process::ExitStatusExt;
+
+        assert_eq!(exit_code(ExitStatus::from_raw(0)), 0);
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGINT)), 130);
+        assert_eq!(exit_code(ExitStatus::from_raw(libc::SIGTERM)), 143);
+    }
+
     #[test]
     fn allows_binaries_inside_install_dir() {
         let temp = tempdir().unwrap();
@@ -71,4 +301,74 @@ mod tests {
         let result = ensure_binary_within_dir(&binary_path, temp.path());
         assert!(result.is_err());
     }
+
+    #[test]
+    fn check_checksum_refuses_modified_binaries() {
+        let temp = tempdir().unwrap();
+        let binary_path = temp.path().join("tool");
+        fs::write(&binary_path, b"#!/bin/sh\n").unwrap();
+        let record = InstallRecord {
+            sha256: Some(file_sha256(&binary_path).unwrap()),
+            ..InstallRecord::default()
+        };
+
+        assert!(check_checksum(&binary_path, Some(&record), true).is_ok());
+        fs::write(&binary_path, b"#!/bin/sh\nrm -rf ~\n").unwrap();
+        let err = check_checksum(&binary_path, Some(&record), false).unwrap_err();
+        assert!(err.to_string().contains("modified"), "{err}");
+    }
+
+    #[test]
+    fn check_checksum_requires_a_record_only_when_asked() {
+        let temp = tempdir().unwrap();
+        let binary_path = temp.path().join("tool");
+        fs::write(&binary_path, b"#!/bin/sh\n").unwrap();
+
+        assert!(check_checksum(&binary_path, None, false).is_ok());
+        assert!(check_checksum(&binary_path, None, true).is_err());
+        let unrecorded = InstallRecord::default();
+        assert!(check_checksum(&binary_path, Some(&unrecorded), true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permission_problem_flags_foreign_owners_and_writable_paths() {
+        let path = Path::new("/store/bin/tool");
+        assert_eq!(permission_problem(path, 1000, 0o755, false, 1000), None);
+        assert_eq!(permission_problem(path, 0, 0o755, true, 1000), None);
+        assert_eq!(permission_problem(path, 0, 0o1777, true, 1000), None);
+
+        let foreign = permission_problem(path, 1001, 0o755, false, 1000).unwrap();
+        assert!(foreign.contains("owned by uid 1001"), "{foreign}");
+
+        let group = permission_problem(path, 1000, 0o775, true, 1000).unwrap();
+        assert!(group.contains("writable by its group"), "{group}");
+        assert!(group.contains("chmod go-w /store/bin/tool"), "{group}");
+
+        let sticky_file = permission_problem(path, 1000, 0o1666, false, 1000).unwrap();
+        assert!(
+            sticky_file.contains("group and other users"),
+            "{sticky_file}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ensure_secure_permissions_checks_the_directory_chain() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempdir().unwrap();
+        let bin_dir = temp.path().join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let binary_path = bin_dir.join("tool");
+        fs::write(&binary_path, b"#!/bin/sh\n").unwrap();
+        fs::set_permissions(&binary_path, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&bin_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(temp.path(), fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(ensure_secure_permissions(&binary_path).is_ok());
+
+        fs::set_permissions(&bin_dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let err = ensure_secure_permissions(&binary_path).unwrap_err();
+        assert!(err.to_string().contains("writable"), "{err}");
+    }
 }
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result, anyhow};
+use std::process::Command;
+
+use crate::target::GitSource;
+
+/// Resolve the branch, tag or revision of a git source to a full commit hash.
+pub fn resolve_commit(source: &GitSource) -> Result<String> {
+    let reference = source.reference.as_deref().unwrap_or("HEAD");
+
+    if is_full_commit(reference) {
+        return Ok(reference.to_ascii_lowercase());
+    }
+
+    let output = Command::new("git")
+        .args(["ls-remote", "--", &source.url, reference])
+        .output()
+        .context("failed to invoke git; is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git ls-remote {} failed: {}",
+            source.url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let listing = String::from_utf8_lossy(&output.stdout);
+    if let Some(commit) = select_ref(&listing, reference) {
+        return Ok(commit);
+    }
+
+    if is_abbreviated_commit(reference) {
+        return resolve_abbreviated(source, reference);
+    }
+
+    Err(anyhow!(
+        "no branch, tag or revision named `{reference}` in {}",
+        source.url
+    ))
+}
+
+/// Abbreviated form of a commit hash used when naming stored binaries.
+pub fn short_commit(commit: &str) -> &str {
+    &commit[..commit.len().min(12)]
+}
+
+/// Pick the commit for `reference` out of `git ls-remote` output, preferring
+/// branches over tags and peeled tags over the tag objects themselves.
+fn select_ref(listing: &str, reference: &str) -> Option<String> {
+    let refs: Vec<(&str, &str)> = listing
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+
+    let candidates = [
+        reference.to_owned(),
+        format!("refs/heads/{reference}"),
+        format!("refs/tags/{reference}^{{}}"),
+        format!("refs/tags/{reference}"),
+    ];
+
+    candidates.iter().find_map(|candidate| {
+        refs.iter()
+            .find(|(_, name)| name == candidate)
+            .map(|(commit, _)| commit.to_string())
+    })
+}
+
+/// Abbreviated revisions are not advertised by the remote, so fetch the history
+/// into a throwaway bare repository and let git expand it.
+fn resolve_abbreviated(source: &GitSource, reference: &str) -> Result<String> {
+    let temp_dir = tempfile::tempdir().context("failed to create temp directory")?;
+
+    let status = Command::new("git")
+        .args(["clone", "--quiet", "--bare", "--filter=blob:none", "--"])
+        .arg(&source.url)
+        .arg(temp_dir.path())
+        .status()
+        .context("failed to invoke git; is it installed?")?;
+    if !status.success() {
+        return Err(anyhow!("failed to clone {}", source.url));
+    }
+
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(temp_dir.path())
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{reference}^{{commit}}"))
+        .output()
+        .context("failed to invoke git rev-parse")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "no branch, tag or revision named `{reference}` in {}",
+            source.url
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+fn is_full_commit(reference: &str) -> bool {
+    reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_abbreviated_commit(reference: &str) -> bool {
+    (4..40).contains(&reference.len()) && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "\
+1111111111111111111111111111111111111111\tHEAD
+2222222222222222222222222222222222222222\trefs/heads/main
+3333333333333333333333333333333333333333\trefs/tags/v1.0.0
+4444444444444444444444444444444444444444\trefs/tags/v1.0.0^{}
+";
+
+    #[test]
+    fn select_ref_prefers_branches_and_peeled_tags() {
+        assert_eq!(
+            select_ref(LISTING, "HEAD").as_deref(),
+            Some("1111111111111111111111111111111111111111")
+        );
+        assert_eq!(
+            select_ref(LISTING, "main").as_deref(),
+            Some("2222222222222222222222222222222222222222")
+        );
+        assert_eq!(
+            select_ref(LISTING, "v1.0.0").as_deref(),
+            Some("4444444444444444444444444444444444444444")
+        );
+        assert_eq!(select_ref(LISTING, "missing"), None);
+    }
+
+    #[test]
+    fn recognizes_commit_hashes() {
+        assert!(is_full_commit("0123456789abcdef0123456789abcdef01234567"));
+        assert!(!is_full_commit("main"));
+        assert!(is_abbreviated_commit("deadbeef"));
+        assert!(!is_abbreviated_commit("v1.0"));
+        assert_eq!(
+            short_commit("0123456789abcdef0123456789abcdef01234567"),
+            "0123456789ab"
+        );
+    }
+}
@@ -4,26 +4,222 @@ use std::fs;
 use std::path::Path;
 use std::process::Command;
 
+use crate::build_cache;
+use crate::cargo_config::{CargoConfig, crates_io_replacement};
 use crate::cli::Cli;
-use crate::paths::get_install_dir;
-use crate::target::Target;
-use crate::versions::versioned_binary_path;
+use crate::config::Config;
+use crate::elf;
+use crate::git::short_commit;
+use crate::local::LocalPackage;
+use crate::manifest::{InstallRecord, file_sha256, lookup, record_install, remove_install};
+use crate::paths::{create_store_dir, get_install_dir};
+use crate::registry::{fetch_crate_source, fetch_rust_version, is_yanked, known_crate_versions};
+use crate::smoke::smoke_test;
+use crate::target::{GitSource, Target};
+use crate::toolchain::{Toolchain, host_triple, parse_rust_version, select_toolchain};
+use crate::versions::{git_binary_path, path_binary_path, versioned_binary_path};
 
 pub fn ensure_installed(target: &Target, cli: &Cli, version: &Version) -> Result<()> {
-    if !cli.build_from_source && which::which("cargo-binstall").is_ok() {
-        install_with_binstall(target, cli, version)
-    } else {
+    let target_path = versioned_binary_path(&target.binary, version, target.platform.as_deref())?;
+    replacing(&target_path, || install_release(target, cli, version))
+}
+
+fn install_release(target: &Target, cli: &Cli, version: &Version) -> Result<()> {
+    if is_yanked_release(&target.crate_name, version)? {
+        install_yanked(target, cli, version)?;
+    } else if will_build_from_source(cli) {
         log_fallback_reason(cli, target, version);
-        install_with_cargo(target, cli, version)
+        install_with_cargo(target, cli, version, None)?;
+    } else {
+        install_prebuilt(target, cli, version)?;
+    }
+
+    let target_path = versioned_binary_path(&target.binary, version, target.platform.as_deref())?;
+    smoke_test_or_roll_back(target, &target_path, Some(version))
+}
+
+/// Run `install`, which puts a new binary at `target_path`. A binary already there,
+/// e.g. when `--force` reinstalls a working version, is set aside meanwhile and put
+/// back with its manifest entry if the install or its smoke test fails.
+fn replacing(target_path: &Path, install: impl FnOnce() -> Result<()>) -> Result<()> {
+    if !target_path.exists() {
+        return install();
+    }
+
+    let install_dir = get_install_dir()?;
+    let aside = tempfile::tempdir_in(&install_dir).with_context(|| {
+        format!(
+            "failed to create a temp directory in {}",
+            install_dir.display()
+        )
+    })?;
+    let backup = aside.path().join("previous");
+    let record = lookup(target_path)?;
+    fs::rename(target_path, &backup)
+        .with_context(|| format!("failed to set aside {}", target_path.display()))?;
+
+    let result = install();
+    if result.is_err() {
+        if let Err(restore_err) = restore(target_path, &backup, record) {
+            // Leave the previous binary where it was set aside rather than delete it
+            let kept = aside.keep();
+            return result.context(format!(
+                "could not restore the previous install ({restore_err:#}); it is kept in {}",
+                kept.display()
+            ));
+        }
+        eprintln!("Kept the previous install at {}", target_path.display());
+    }
+    result
+}
+
+/// Put the binary set aside at `backup` back at `target_path`, with its manifest entry.
+fn restore(target_path: &Path, backup: &Path, record: Option<InstallRecord>) -> Result<()> {
+    if target_path.exists() {
+        fs::remove_file(target_path)
+            .with_context(|| format!("failed to remove {}", target_path.display()))?;
+    }
+    fs::rename(backup, target_path)
+        .with_context(|| format!("failed to restore {}", target_path.display()))?;
+    match record {
+        Some(record) => record_install(target_path, record),
+        None => remove_install(target_path),
+    }
+}
+
+/// Run the configured smoke test against a new install, removing the binary and its
+/// manifest entry when it fails so the next run starts from scratch.
+fn smoke_test_or_roll_back(
+    target: &Target,
+    target_path: &Path,
+    version: Option<&Version>,
+) -> Result<()> {
+    let config = Config::load()?.smoke_test;
+    // Binaries for another target cannot be run here
+    if !config.enabled || target.platform.is_some() {
+        return Ok(());
+    }
+
+    if let Err(err) = smoke_test(
+        &config,
+        &target.crate_name,
+        &target.binary,
+        target_path,
+        version,
+    ) {
+        fs::remove_file(target_path)
+            .with_context(|| format!("failed to remove {}", target_path.display()))?;
+        remove_install(target_path)?;
+        return Err(err.context(format!(
+            "smoke test of {} failed; the install was rolled back",
+            target.crate_name
+        )));
+    }
+    Ok(())
+}
+
+/// Install with `cargo-binstall`, then check that the binary can actually run here.
+/// An unusable artifact is replaced by the musl build when there is one, and
+/// otherwise by a source build.
+fn install_prebuilt(target: &Target, cli: &Cli, version: &Version) -> Result<()> {
+    install_with_binstall(target, cli, version, None)?;
+
+    let target_path = versioned_binary_path(&target.binary, version, target.platform.as_deref())?;
+    let problems = host_incompatibilities(target, &target_path);
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let diagnosis = format!(
+        "the prebuilt {}@{} cannot run on this host:\n{}",
+        target.crate_name,
+        version,
+        problems
+            .iter()
+            .map(|problem| format!("  - {problem}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    eprintln!("warning: {diagnosis}");
+
+    let installed_musl = lookup(&target_path)?
+        .and_then(|record| record.libc)
+        .is_some_and(|libc| libc == "musl");
+    if !installed_musl
+        && let Some(musl) = host_triple(None).ok().and_then(|host| musl_triple(&host))
+    {
+        eprintln!("Retrying with the {musl} build");
+        if install_with_binstall(target, cli, version, Some(&musl)).is_ok()
+            && host_incompatibilities(target, &target_path).is_empty()
+        {
+            return Ok(());
+        }
+    }
+
+    eprintln!(
+        "Building {}@{} from source instead",
+        target.crate_name, version
+    );
+    install_with_cargo(target, cli, version, None).map_err(|err| {
+        // Never leave a binary behind that we know cannot run
+        let _ = fs::remove_file(&target_path);
+        err.context(diagnosis)
+    })
+}
+
+/// Problems that would stop the installed binary from running on this host. Binaries
+/// for another `--target` are not expected to run here, and non-ELF or unreadable
+/// files are not judged.
+fn host_incompatibilities(target: &Target, path: &Path) -> Vec<String> {
+    if target.platform.is_some() {
+        return vec![];
+    }
+    match elf::inspect(path) {
+        Ok(Some(info)) => elf::incompatibilities(&info, path, &elf::Host::detect()),
+        _ => vec![],
     }
 }
 
+/// Resolution only picks a yanked release for an exact pin, and has just fetched the
+/// registry metadata that says so.
+fn is_yanked_release(crate_name: &str, version: &Version) -> Result<bool> {
+    Ok(known_crate_versions(crate_name)?.is_some_and(|versions| is_yanked(&versions, version)))
+}
+
+/// Neither `cargo install` nor `cargo-binstall` resolves a yanked release, so build
+/// it from its published `.crate` instead.
+fn install_yanked(target: &Target, cli: &Cli, version: &Version) -> Result<()> {
+    eprintln!(
+        "{}@{} has been yanked, which cargo install refuses; building it from its .crate",
+        target.crate_name, version
+    );
+    let source_dir = tempfile::tempdir().context("failed to create temp directory")?;
+    let package = fetch_crate_source(&target.crate_name, version, source_dir.path())?;
+    install_with_cargo(target, cli, version, Some(&package))
+}
+
+/// Registry crates are built from source when asked to, when a toolchain is chosen,
+/// when crates.io is replaced by another source (which `cargo-binstall` would bypass),
+/// or when `cargo-binstall` is unavailable. A cargo config that cannot be read does not
+/// count as replacing crates.io; a source build still fails on it.
+pub fn will_build_from_source(cli: &Cli) -> bool {
+    cli.build_from_source
+        || cli.toolchain.is_some()
+        || crates_io_replacement().is_some()
+        || which::which("cargo-binstall").is_err()
+}
+
 fn log_fallback_reason(cli: &Cli, target: &Target, version: &Version) {
-    if cli.build_from_source {
+    if cli.build_from_source || cli.toolchain.is_some() {
         eprintln!(
             "Building {}@{} from source with cargo install",
             target.crate_name, version
         );
+    } else if which::which("cargo-binstall").is_ok() {
+        eprintln!(
+            "crates.io is replaced in your cargo config; building {}@{} with cargo install",
+            target.crate_name, version
+        );
     } else {
         eprintln!(
             "cargo-binstall not found; falling back to cargo install for {}@{}",
@@ -32,7 +228,13 @@ fn log_fallback_reason(cli: &Cli, target: &Target, version: &Version) {
     }
 }
 
-fn install_with_binstall(target: &Target, cli: &Cli, version: &Version) -> Result<()> {
+/// `targets` overrides the triples `cargo-binstall` looks for artifacts for.
+fn install_with_binstall(
+    target: &Target,
+    cli: &Cli,
+    version: &Version,
+    targets: Option<&str>,
+) -> Result<()> {
     let install_dir = get_install_dir()?;
     ensure_bin_dir(&install_dir)?;
 
@@ -43,9 +245,20 @@ fn install_with_binstall(target: &Target, cli: &Cli, version: &Version) -> Resul
     }
     cmd.arg("--no-confirm");
     cmd.arg("--force");
-    if let Some(bin) = &cli.bin {
+    if target.explicit_binary {
         cmd.arg("--bin");
-        cmd.arg(bin);
+        cmd.arg(&target.binary);
+    }
+    let targets = match targets.or(target.platform.as_deref()) {
+        Some(targets) => Some(targets.to_owned()),
+        None if Config::load()?.install.prefer_musl => {
+            host_triple(None).ok().and_then(|host| musl_first(&host))
+        }
+        None => None,
+    };
+    if let Some(targets) = &targets {
+        cmd.arg("--targets");
+        cmd.arg(targets);
     }
     cmd.arg(format!("{}@{}", target.crate_name, version));
 
@@ -61,25 +274,44 @@ fn install_with_binstall(target: &Target, cli: &Cli, version: &Version) -> Resul
         install_dir.display()
     );
 
-    let status = cmd.status().context("failed to invoke cargo-binstall")?;
-    if status.success() {
-        finalize_installation(&install_dir, &target.binary, version)
-    } else {
-        Err(anyhow!(
-            "cargo-binstall exited with status code {}",
-            status
-                .code()
-                .map(|c| c.to_string())
-                .unwrap_or_else(|| "signal".to_string())
-        ))
-    }
+    let target_path = versioned_binary_path(&target.binary, version, target.platform.as_deref())?;
+    let record = InstallRecord {
+        crate_name: target.crate_name.clone(),
+        source: "registry".to_string(),
+        installer: "cargo-binstall".to_string(),
+        version: Some(version.to_string()),
+        target: target.platform.clone(),
+        ..InstallRecord::default()
+    };
+    run_installer(
+        &mut cmd,
+        &install_dir,
+        &target.binary,
+        &target_path,
+        targets.as_deref(),
+        record,
+    )
 }
 
-fn install_with_cargo(target: &Target, cli: &Cli, version: &Version) -> Result<()> {
+/// Build a registry release with `cargo install`, from `source` when its unpacked
+/// `.crate` is already on disk.
+fn install_with_cargo(
+    target: &Target,
+    cli: &Cli,
+    version: &Version,
+    source: Option<&Path>,
+) -> Result<()> {
     let install_dir = get_install_dir()?;
     ensure_bin_dir(&install_dir)?;
 
-    // Create a temporary directory for the build
+    let rust_version = fetch_rust_version(&target.crate_name, version)?;
+    let toolchain = select_toolchain(
+        cli.toolchain.as_deref(),
+        &format!("{}@{}", target.crate_name, version),
+        rust_version.as_ref(),
+    )?;
+
+    // Scratch space for the build; also the target directory unless the build cache is on
     let temp_dir = tempfile::tempdir().context("failed to create temp directory")?;
 
     let mut cmd = Command::new("cargo");
@@ -87,40 +319,288 @@ fn install_with_cargo(target: &Target, cli: &Cli, version: &Version) -> Result<(
     if cli.quiet {
         cmd.arg("--quiet");
     }
-    if cli.force {
+    if cli.force || source.is_some() {
         cmd.arg("--force");
     }
     cmd.arg("--root");
     cmd.arg(&install_dir);
-    cmd.arg(&target.crate_name);
-    cmd.arg("--version");
-    cmd.arg(version.to_string());
-    if let Some(bin) = &cli.bin {
+    if let Some(source) = source {
+        cmd.arg("--path");
+        cmd.arg(source);
+    } else {
+        cmd.arg(&target.crate_name);
+        cmd.arg("--version");
+        cmd.arg(version.to_string());
+    }
+    if target.explicit_binary {
         cmd.arg("--bin");
-        cmd.arg(bin);
+        cmd.arg(&target.binary);
+    }
+    if let Some(triple) = &target.platform {
+        cmd.arg("--target");
+        cmd.arg(triple);
     }
 
-    // Use temp directory for target build directory and sanitize environment
-    cmd.env("CARGO_TARGET_DIR", temp_dir.path());
-    sanitize_cargo_env(&mut cmd, &install_dir);
+    let config = Config::load()?;
+    prepare_source_build(&mut cmd, &install_dir, temp_dir.path(), &toolchain, &config)?;
 
     eprintln!(
-        "Installing {}@{} with cargo install{} to {}",
+        "Installing {}@{} with cargo install{} using {} to {}",
         target.crate_name,
         version,
         if cli.quiet { " (quiet)" } else { "" },
+        toolchain.describe(),
         install_dir.display()
     );
 
-    let status = cmd.status().context("failed to invoke cargo install")?;
+    let target_path = versioned_binary_path(&target.binary, version, target.platform.as_deref())?;
+    let record = source_build_record(target, "registry".to_string(), &toolchain);
+    let record = InstallRecord {
+        version: Some(version.to_string()),
+        ..record
+    };
+    let result = run_installer(
+        &mut cmd,
+        &install_dir,
+        &target.binary,
+        &target_path,
+        target.platform.as_deref(),
+        record,
+    );
+    build_cache::trim_after_build(&config);
+    result
+}
+
+/// Build a git source at `commit` with `cargo install --git`. Git sources are always
+/// built from source since prebuilt artifacts only exist for published releases.
+pub fn ensure_git_installed(
+    target: &Target,
+    cli: &Cli,
+    source: &GitSource,
+    commit: &str,
+) -> Result<()> {
+    let target_path = git_binary_path(&target.binary, commit, target.platform.as_deref())?;
+    replacing(&target_path, || install_git(target, cli, source, commit))
+}
+
+fn install_git(target: &Target, cli: &Cli, source: &GitSource, commit: &str) -> Result<()> {
+    let install_dir = get_install_dir()?;
+    ensure_bin_dir(&install_dir)?;
+
+    // The crate's rust-version is unknown until cargo has checked out the commit
+    let toolchain = select_toolchain(cli.toolchain.as_deref(), &target.crate_name, None)?;
 
-    // Temp directory will be automatically cleaned up when temp_dir goes out of scope
+    let temp_dir = tempfile::tempdir().context("failed to create temp directory")?;
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg("install");
+    if cli.quiet {
+        cmd.arg("--quiet");
+    }
+    // cargo skips commits it believes are installed, but we move binaries out of
+    // its bin directory, so always ask it to rebuild.
+    cmd.arg("--force");
+    cmd.arg("--root");
+    cmd.arg(&install_dir);
+    cmd.arg("--git");
+    cmd.arg(&source.url);
+    cmd.arg("--rev");
+    cmd.arg(commit);
+    if let Some(package) = &source.package {
+        cmd.arg(package);
+    }
+    if target.explicit_binary {
+        cmd.arg("--bin");
+        cmd.arg(&target.binary);
+    }
+    if let Some(triple) = &target.platform {
+        cmd.arg("--target");
+        cmd.arg(triple);
+    }
+
+    let config = Config::load()?;
+    prepare_source_build(&mut cmd, &install_dir, temp_dir.path(), &toolchain, &config)?;
+
+    eprintln!(
+        "Installing {} from {}#{} with cargo install{} using {} to {}",
+        target.crate_name,
+        source.url,
+        short_commit(commit),
+        if cli.quiet { " (quiet)" } else { "" },
+        toolchain.describe(),
+        install_dir.display()
+    );
+
+    let target_path = git_binary_path(&target.binary, commit, target.platform.as_deref())?;
+    let record = source_build_record(target, format!("git+{}#{commit}", source.url), &toolchain);
+    let result = run_installer(
+        &mut cmd,
+        &install_dir,
+        &target.binary,
+        &target_path,
+        target.platform.as_deref(),
+        record,
+    );
+    build_cache::trim_after_build(&config);
+    result?;
+    smoke_test_or_roll_back(target, &target_path, None)
+}
+
+/// Build a local crate with `cargo install --path`.
+pub fn ensure_path_installed(
+    target: &Target,
+    cli: &Cli,
+    package: &LocalPackage,
+    source_hash: &str,
+) -> Result<()> {
+    let target_path = path_binary_path(&target.binary, source_hash, target.platform.as_deref())?;
+    replacing(&target_path, || {
+        install_path(target, cli, package, source_hash)
+    })
+}
+
+fn install_path(
+    target: &Target,
+    cli: &Cli,
+    package: &LocalPackage,
+    source_hash: &str,
+) -> Result<()> {
+    let install_dir = get_install_dir()?;
+    ensure_bin_dir(&install_dir)?;
+
+    let rust_version = package.rust_version.as_deref().and_then(parse_rust_version);
+    let toolchain = select_toolchain(
+        cli.toolchain.as_deref(),
+        &package.name,
+        rust_version.as_ref(),
+    )?;
+
+    let temp_dir = tempfile::tempdir().context("failed to create temp directory")?;
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg("install");
+    if cli.quiet {
+        cmd.arg("--quiet");
+    }
+    cmd.arg("--force");
+    cmd.arg("--root");
+    cmd.arg(&install_dir);
+    cmd.arg("--path");
+    cmd.arg(&package.manifest_dir);
+    if target.explicit_binary {
+        cmd.arg("--bin");
+        cmd.arg(&target.binary);
+    }
+    if let Some(triple) = &target.platform {
+        cmd.arg("--target");
+        cmd.arg(triple);
+    }
+
+    let config = Config::load()?;
+    prepare_source_build(&mut cmd, &install_dir, temp_dir.path(), &toolchain, &config)?;
+
+    eprintln!(
+        "Installing {} from {} with cargo install{} using {} to {}",
+        target.crate_name,
+        package.manifest_dir.display(),
+        if cli.quiet { " (quiet)" } else { "" },
+        toolchain.describe(),
+        install_dir.display()
+    );
+
+    let target_path = path_binary_path(&target.binary, source_hash, target.platform.as_deref())?;
+    let source = format!("path+{}", package.manifest_dir.display());
+    let record = source_build_record(target, source, &toolchain);
+    let result = run_installer(
+        &mut cmd,
+        &install_dir,
+        &target.binary,
+        &target_path,
+        target.platform.as_deref(),
+        record,
+    );
+    build_cache::trim_after_build(&config);
+    result?;
+    smoke_test_or_roll_back(target, &target_path, None)
+}
+
+/// On Linux gnu hosts, the musl triple followed by the host triple, the order in which
+/// `cargo-binstall` should look for artifacts when musl builds are preferred.
+fn musl_first(host: &str) -> Option<String> {
+    Some(format!("{},{host}", musl_triple(host)?))
+}
+
+/// The musl counterpart of a Linux gnu triple.
+fn musl_triple(host: &str) -> Option<String> {
+    let (arch, abi) = host.split_once("-unknown-linux-gnu")?;
+    Some(format!("{arch}-unknown-linux-musl{abi}"))
+}
+
+/// Sandbox a `cargo install` build and point it at its target directory: the shared
+/// per-toolchain build cache when enabled, otherwise the throwaway `scratch_dir`.
+fn prepare_source_build(
+    cmd: &mut Command,
+    install_dir: &Path,
+    scratch_dir: &Path,
+    toolchain: &Toolchain,
+    config: &Config,
+) -> Result<()> {
+    // Sanitizing removes any inherited CARGO_TARGET_DIR, so set ours afterwards
+    sanitize_cargo_env(cmd, install_dir);
+    let target_dir = if config.build_cache.enabled {
+        build_cache::target_dir(toolchain)?
+    } else {
+        scratch_dir.join("target")
+    };
+    cmd.env("CARGO_TARGET_DIR", target_dir);
+    if let Some(wrapper) = &config.build_cache.rustc_wrapper {
+        cmd.env("RUSTC_WRAPPER", wrapper);
+    }
+    CargoConfig::load()?.apply(cmd, scratch_dir)?;
+    toolchain.apply(cmd);
+    Ok(())
+}
+
+fn source_build_record(target: &Target, source: String, toolchain: &Toolchain) -> InstallRecord {
+    InstallRecord {
+        crate_name: target.crate_name.clone(),
+        source,
+        installer: "cargo install".to_string(),
+        toolchain: toolchain.name.clone(),
+        rustc: Some(toolchain.rustc.to_string()),
+        target: target.platform.clone(),
+        ..InstallRecord::default()
+    }
+}
+
+/// Run an installer command and, if it succeeds, move the binary it produced into
+/// the versioned store at `target_path` and record how it was built. `triples` are
+/// the target triples the installer was asked for, in order of preference.
+fn run_installer(
+    cmd: &mut Command,
+    install_dir: &Path,
+    binary: &str,
+    target_path: &Path,
+    triples: Option<&str>,
+    mut record: InstallRecord,
+) -> Result<()> {
+    let installer = record.installer.clone();
+    let status = cmd
+        .status()
+        .with_context(|| format!("failed to invoke {installer}"))?;
 
     if status.success() {
-        finalize_installation(&install_dir, &target.binary, version)
+        finalize_installation(install_dir, binary, target_path)?;
+        // Best effort: a binary we cannot parse is simply recorded without a libc
+        record.libc = elf::inspect(target_path)
+            .ok()
+            .flatten()
+            .map(|info| recorded_libc(&info, triples).to_string());
+        record.sha256 = Some(file_sha256(target_path)?);
+        record_install(target_path, record)
     } else {
         Err(anyhow!(
-            "cargo install exited with status code {}",
+            "{installer} exited with status code {}",
             status
                 .code()
                 .map(|c| c.to_string())
@@ -129,6 +609,19 @@ fn install_with_cargo(target: &Target, cli: &Cli, version: &Version) -> Result<(
     }
 }
 
+/// The libc to record for a fresh install. A static binary without musl's tell is
+/// still the musl build when a musl triple was the first choice, since gnu release
+/// builds are linked dynamically.
+fn recorded_libc(info: &elf::ElfInfo, triples: Option<&str>) -> &'static str {
+    let musl_first = triples
+        .and_then(|triples| triples.split(',').next())
+        .is_some_and(|triple| triple.trim().contains("-musl"));
+    match info.libc() {
+        "static" if musl_first => "musl",
+        libc => libc,
+    }
+}
+
 /// Sanitize the environment for cargo commands to ensure complete sandboxing.
 /// Removes any Cargo-related environment variables that could leak into the installation
 /// and sets only the variables we explicitly want.
@@ -152,38 +645,26 @@ fn sanitize_cargo_env(cmd: &mut Command, install_dir: &std::path::Path) {
     cmd.env("CARGO_INSTALL_ROOT", install_dir);
 }
 
-fn finalize_installation(install_dir: &Path, binary: &str, version: &Version) -> Result<()> {
+fn finalize_installation(install_dir: &Path, binary: &str, target_path: &Path) -> Result<()> {
     let bin_dir = install_dir.join("bin");
     let installed_path = {
         let candidate = bin_dir.join(binary);
-        if candidate.exists() {
+        // Windows binaries carry `.exe`, including ones cross-installed with `--target`
+        let exe_candidate = bin_dir.join(format!("{binary}.exe"));
+        if candidate.is_file() {
             candidate
+        } else if exe_candidate.is_file() {
+            exe_candidate
         } else {
-            #[cfg(windows)]
-            {
-                let exe_candidate = bin_dir.join(binary).with_extension("exe");
-                if exe_candidate.exists() {
-                    exe_candidate
-                } else {
-                    return Err(anyhow!(
-                        "expected installer to create {}, but it was not found",
-                        candidate.display()
-                    ));
-                }
-            }
-            #[cfg(not(windows))]
-            {
-                return Err(anyhow!(
-                    "expected installer to create {}, but it was not found",
-                    candidate.display()
-                ));
-            }
+            return Err(anyhow!(
+                "expected installer to create {}, but it was not found",
+                candidate.display()
+            ));
         }
     };
 
-    let target_path = versioned_binary_path(binary, version)?;
     if target_path.exists() {
-        fs::remove_file(&target_path).with_context(|| {
+        fs::remove_file(target_path).with_context(|| {
             format!(
                 "failed to replace existing installation {}",
                 target_path.display()
@@ -191,7 +672,7 @@ fn finalize_installation(install_dir: &Path, binary: &str, version: &Version) ->
         })?;
     }
 
-    fs::rename(&installed_path, &target_path).with_context(|| {
+    fs::rename(&installed_path, target_path).with_context(|| {
         format!(
             "failed to move installed binary from {} to {}",
             installed_path.display(),
@@ -199,17 +680,102 @@ fn finalize_installation(install_dir: &Path, binary: &str, version: &Version) ->
         )
     })?;
 
+    // A permissive umask can leave the binary group-writable, which the executor refuses
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = fs::metadata(target_path)?.permissions();
+        permissions.set_mode(permissions.mode() & !0o022);
+        fs::set_permissions(target_path, permissions).with_context(|| {
+            format!(
+                "failed to restrict permissions of {}",
+                target_path.display()
+            )
+        })?;
+    }
+
     Ok(())
 }
 
 fn ensure_bin_dir(install_dir: &Path) -> Result<()> {
     let bin_dir = install_dir.join("bin");
-    fs::create_dir_all(&bin_dir).with_context(|| format!("failed to create {}", bin_dir.display()))
+    create_store_dir(&bin_dir).with_context(|| format!("failed to create {}", bin_dir.display()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::env_lock;
+    use std::env;
+
+    #[test]
+    fn musl_first_prefers_musl_on_gnu_hosts() {
+        assert_eq!(
+            musl_first("x86_64-unknown-linux-gnu").as_deref(),
+            Some("x86_64-unknown-linux-musl,x86_64-unknown-linux-gnu")
+        );
+        assert_eq!(
+            musl_first("armv7-unknown-linux-gnueabihf").as_deref(),
+            Some("armv7-unknown-linux-musleabihf,armv7-unknown-linux-gnueabihf")
+        );
+        assert_eq!(musl_first("aarch64-apple-darwin"), None);
+        assert_eq!(musl_first("x86_64-unknown-linux-musl"), None);
+    }
+
+    #[test]
+    fn replacing_reports_both_the_install_and_the_restore_failure() {
+        let _guard = env_lock().lock().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        unsafe {
+            env::set_var("CARGOX_INSTALL_DIR", temp.path());
+        }
+        let target_path = temp.path().join("tool-1.0.0");
+        fs::write(&target_path, "previous").unwrap();
+
+        // A directory in the binary's place cannot be removed with remove_file
+        let result = replacing(&target_path, || {
+            fs::create_dir(&target_path).unwrap();
+            Err(anyhow!("install failed"))
+        });
+        unsafe {
+            env::remove_var("CARGOX_INSTALL_DIR");
+        }
+
+        let err = format!("{:#}", result.unwrap_err());
+        assert!(
+            err.contains("could not restore the previous install")
+                && err.contains("install failed"),
+            "{err}"
+        );
+        let kept = fs::read_dir(temp.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path().join("previous"))
+            .find(|path| path.is_file())
+            .unwrap();
+        assert_eq!(fs::read_to_string(kept).unwrap(), "previous");
+    }
+
+    #[test]
+    fn recorded_libc_takes_static_binaries_for_musl_first_installs() {
+        let dynamic = elf::ElfInfo {
+            interpreter: Some("/lib64/ld-linux-x86-64.so.2".to_string()),
+            ..elf::ElfInfo::default()
+        };
+        let fully_static = elf::ElfInfo::default();
+        let musl_first = Some("x86_64-unknown-linux-musl,x86_64-unknown-linux-gnu");
+
+        assert_eq!(recorded_libc(&dynamic, musl_first), "gnu");
+        assert_eq!(recorded_libc(&fully_static, musl_first), "musl");
+        assert_eq!(
+            recorded_libc(&fully_static, Some("aarch64-unknown-linux-musl")),
+            "musl"
+        );
+        assert_eq!(
+            recorded_libc(&fully_static, Some("x86_64-unknown-linux-gnu")),
+            "static"
+        );
+        assert_eq!(recorded_libc(&fully_static, None), "static");
+    }
 
     #[test]
     fn sanitize_cargo_env_removes_cargo_variables() {
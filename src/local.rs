@@ -0,0 +1,437 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::target::PathSource;
+
+/// A crate on the local filesystem, as described by `cargo metadata`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalPackage {
+    pub name: String,
+    pub manifest_dir: PathBuf,
+    pub binaries: Vec<String>,
+    pub rust_version: Option<String>,
+    /// Directories of the package and of its path dependencies, inside the workspace
+    /// or not, followed transitively; these are the sources that feed the build.
+    pub source_dirs: Vec<PathBuf>,
+    pub workspace_root: PathBuf,
+}
+
+impl LocalPackage {
+    /// The binary to run when `--bin` is not given: the package's only binary
+    /// target if it has exactly one, otherwise the package name.
+    pub fn default_binary(&self) -> &str {
+        match self.binaries.as_slice() {
+            [only] => only,
+            _ => &self.name,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+    workspace_root: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    name: String,
+    manifest_path: PathBuf,
+    targets: Vec<MetadataTarget>,
+    #[serde(default)]
+    rust_version: Option<String>,
+    #[serde(default)]
+    dependencies: Vec<MetadataDependency>,
+}
+
+#[derive(Deserialize)]
+struct MetadataDependency {
+    /// `dev`, `build`, or `None` for normal dependencies
+    #[serde(default)]
+    kind: Option<String>,
+    /// Set for path dependencies
+    #[serde(default)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct MetadataTarget {
+    name: String,
+    kind: Vec<String>,
+}
+
+pub fn inspect_package(source: &PathSource) -> Result<LocalPackage> {
+    let dir = source
+        .path
+        .canonicalize()
+        .with_context(|| format!("failed to resolve crate path {}", source.path.display()))?;
+    let manifest = dir.join("Cargo.toml");
+    if !manifest.is_file() {
+        return Err(anyhow!("no Cargo.toml found in {}", dir.display()));
+    }
+
+    let output = Command::new("cargo")
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version",
+            "1",
+            "--manifest-path",
+        ])
+        .arg(&manifest)
+        .output()
+        .context("failed to invoke cargo metadata")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "cargo metadata failed for {}: {}",
+            manifest.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let metadata: Metadata =
+        serde_json::from_slice(&output.stdout).context("failed to parse cargo metadata output")?;
+    select_package(metadata, &dir, source.package.as_deref())
+}
+
+fn select_package(metadata: Metadata, dir: &Path, package: Option<&str>) -> Result<LocalPackage> {
+    let selected = match package {
+        Some(name) => metadata.packages.iter().find(|pkg| pkg.name == name),
+        None => metadata.packages.iter().find(|pkg| {
+            pkg.manifest_path
+                .parent()
+                .and_then(|parent| parent.canonicalize().ok())
+                .as_deref()
+                == Some(dir)
+        }),
+    };
+
+    let Some(selected) = selected else {
+        return Err(match package {
+            Some(name) => anyhow!("no package named `{name}` in {}", dir.display()),
+            None => anyhow!(
+                "{} is a virtual workspace; name the member to run, e.g. `cargox --path {} <crate>`",
+                dir.display(),
+                dir.display()
+            ),
+        });
+    };
+
+    let manifest_dir = selected
+        .manifest_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| dir.to_path_buf());
+    let binaries = selected
+        .targets
+        .iter()
+        .filter(|target| target.kind.iter().any(|kind| kind == "bin"))
+        .map(|target| target.name.clone())
+        .collect();
+    let source_dirs = package_source_dirs(&metadata.packages, &manifest_dir)?;
+
+    Ok(LocalPackage {
+        name: selected.name.clone(),
+        manifest_dir,
+        binaries,
+        rust_version: selected.rust_version.clone(),
+        source_dirs,
+        workspace_root: metadata.workspace_root,
+    })
+}
+
+/// `package_dir` and the directories of its path dependencies, in turn. Workspace
+/// members take theirs from `cargo metadata`; packages outside the workspace, which
+/// `cargo metadata --no-deps` does not list, from their manifest.
+fn package_source_dirs(members: &[MetadataPackage], package_dir: &Path) -> Result<Vec<PathBuf>> {
+    let members: Vec<(PathBuf, &MetadataPackage)> = members
+        .iter()
+        .filter_map(|pkg| {
+            let dir = pkg.manifest_path.parent()?.canonicalize().ok()?;
+            Some((dir, pkg))
+        })
+        .collect();
+
+    let mut pending = vec![package_dir.to_path_buf()];
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    while let Some(dir) = pending.pop() {
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("failed to resolve path dependency {}", dir.display()))?;
+        if dirs.contains(&dir) {
+            continue;
+        }
+        match members.iter().find(|(member_dir, _)| *member_dir == dir) {
+            // Dev-dependencies are not built by `cargo install`
+            Some((_, member)) => pending.extend(
+                member
+                    .dependencies
+                    .iter()
+                    .filter(|dep| dep.kind.as_deref() != Some("dev"))
+                    .filter_map(|dep| dep.path.clone()),
+            ),
+            None => pending.extend(manifest_path_dependencies(&dir)?),
+        }
+        dirs.push(dir);
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Path dependencies declared in `dir/Cargo.toml`, other than dev-dependencies.
+fn manifest_path_dependencies(dir: &Path) -> Result<Vec<PathBuf>> {
+    let manifest = dir.join("Cargo.toml");
+    let contents = fs::read_to_string(&manifest)
+        .with_context(|| format!("failed to read {}", manifest.display()))?;
+    let table: toml::Table = contents
+        .parse()
+        .with_context(|| format!("failed to parse {}", manifest.display()))?;
+
+    let platforms = table
+        .get("target")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flat_map(|targets| targets.values().filter_map(toml::Value::as_table));
+    let sections = [&table].into_iter().chain(platforms);
+    Ok(sections
+        .flat_map(|section| {
+            ["dependencies", "build-dependencies"]
+                .into_iter()
+                .filter_map(|key| section.get(key).and_then(toml::Value::as_table))
+        })
+        .flat_map(|dependencies| dependencies.values())
+        .filter_map(|dependency| dependency.get("path").and_then(toml::Value::as_str))
+        .map(|path| dir.join(path))
+        .collect())
+}
+
+/// Hash the sources of the package and its path dependencies together with the
+/// workspace's `Cargo.toml` and `Cargo.lock`, so a cached build is reused until any
+/// of them change, while edits elsewhere in the workspace leave it alone.
+pub fn source_hash(package: &LocalPackage) -> Result<String> {
+    let mut files = Vec::new();
+    for dir in &package.source_dirs {
+        collect_files(dir, &mut files)?;
+    }
+    for name in ["Cargo.toml", "Cargo.lock"] {
+        let path = package.workspace_root.join(name);
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let mut hasher = Sha256::new();
+    for file in &files {
+        let relative = file.strip_prefix(&package.workspace_root).unwrap_or(file);
+        let contents =
+            fs::read(file).with_context(|| format!("failed to read {}", file.display()))?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Recursively collect files under `dir`, skipping build output, hidden directories
+/// such as `.git`, and nested packages, which are hashed only when depended on.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("failed to read directory {}", dir.display()))?;
+
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("failed to inspect {}", path.display()))?;
+
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name == "target" || name.starts_with('.') || path.join("Cargo.toml").is_file() {
+                continue;
+            }
+            collect_files(&path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_package(dir: &Path, name: &str) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+        )
+        .unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+    }
+
+    fn package_at(dir: &Path) -> LocalPackage {
+        LocalPackage {
+            name: "tool".to_string(),
+            manifest_dir: dir.to_path_buf(),
+            binaries: vec!["tool".to_string()],
+            rust_version: None,
+            source_dirs: vec![dir.to_path_buf()],
+            workspace_root: dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn source_hash_changes_with_sources_and_lockfile() {
+        let temp = tempdir().unwrap();
+        write_package(temp.path(), "tool");
+        let package = package_at(temp.path());
+
+        let initial = source_hash(&package).unwrap();
+        assert_eq!(initial, source_hash(&package).unwrap());
+
+        fs::write(temp.path().join("src/main.rs"), "fn main() { () }\n").unwrap();
+        let edited = source_hash(&package).unwrap();
+        assert_ne!(initial, edited);
+
+        fs::write(temp.path().join("Cargo.lock"), "version = 4\n").unwrap();
+        assert_ne!(edited, source_hash(&package).unwrap());
+    }
+
+    /// Path dependencies of a member, with their `kind`.
+    type Dependencies<'a> = &'a [(&'a Path, Option<&'a str>)];
+
+    /// `cargo metadata --no-deps` output for `members`, each a name, a directory and
+    /// the path dependencies listed with it.
+    fn metadata(root: &Path, members: &[(&str, &Path, Dependencies)]) -> Metadata {
+        let packages: Vec<serde_json::Value> = members
+            .iter()
+            .map(|(name, dir, deps)| {
+                let dependencies: Vec<serde_json::Value> = deps
+                    .iter()
+                    .map(|(path, kind)| serde_json::json!({ "path": path, "kind": kind }))
+                    .collect();
+                serde_json::json!({
+                    "name": name,
+                    "manifest_path": dir.join("Cargo.toml"),
+                    "targets": [{ "name": name, "kind": ["bin"] }],
+                    "dependencies": dependencies,
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "packages": packages,
+            "workspace_root": root,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn source_hash_covers_only_the_package_and_its_path_dependencies() {
+        let temp = tempdir().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        write_package(&root, "app");
+        let codegen = root.join("tools/codegen");
+        write_package(&codegen, "codegen");
+        let model = root.join("crates/model");
+        write_package(&model, "model");
+        let fixtures = root.join("crates/fixtures");
+        write_package(&fixtures, "fixtures");
+        let unrelated = root.join("crates/unrelated");
+        write_package(&unrelated, "unrelated");
+        let codegen_deps: Dependencies = &[(&model, None), (&fixtures, Some("dev"))];
+        let metadata = metadata(
+            &root,
+            &[
+                ("app", &root, &[(&codegen, None)]),
+                ("codegen", &codegen, codegen_deps),
+                ("model", &model, &[]),
+                ("fixtures", &fixtures, &[]),
+                ("unrelated", &unrelated, &[]),
+            ],
+        );
+
+        let package = select_package(metadata, &codegen, None).unwrap();
+        assert_eq!(package.name, "codegen");
+        assert_eq!(package.source_dirs, vec![model.clone(), codegen.clone()]);
+
+        let initial = source_hash(&package).unwrap();
+        for dir in [&root, &unrelated, &fixtures] {
+            fs::write(dir.join("src/main.rs"), "fn main() { () }\n").unwrap();
+        }
+        assert_eq!(initial, source_hash(&package).unwrap());
+        fs::write(model.join("src/main.rs"), "fn main() { () }\n").unwrap();
+        assert_ne!(initial, source_hash(&package).unwrap());
+    }
+
+    #[test]
+    fn source_hash_follows_path_dependencies_outside_the_workspace() {
+        let temp = tempdir().unwrap();
+        let root = temp.path().join("tool");
+        write_package(&root, "tool");
+        let shared = temp.path().join("shared");
+        write_package(&shared, "shared");
+        let util = temp.path().join("util");
+        write_package(&util, "util");
+        fs::write(
+            shared.join("Cargo.toml"),
+            "[package]\nname = \"shared\"\nversion = \"0.1.0\"\n\n[dependencies]\nutil = { path = \"../util\" }\n\n[dev-dependencies]\ntool = { path = \"../tool\" }\n",
+        )
+        .unwrap();
+
+        let shared_dep: Dependencies = &[(&shared, None)];
+        let metadata = metadata(&root, &[("tool", &root, shared_dep)]);
+        let package = select_package(metadata, &root.canonicalize().unwrap(), None).unwrap();
+        assert_eq!(
+            package.source_dirs,
+            vec![
+                shared.canonicalize().unwrap(),
+                root.canonicalize().unwrap(),
+                util.canonicalize().unwrap()
+            ]
+        );
+
+        let initial = source_hash(&package).unwrap();
+        fs::write(util.join("src/main.rs"), "fn main() { () }\n").unwrap();
+        assert_ne!(initial, source_hash(&package).unwrap());
+    }
+
+    #[test]
+    fn source_hash_ignores_build_output() {
+        let temp = tempdir().unwrap();
+        write_package(temp.path(), "tool");
+        let package = package_at(temp.path());
+
+        let initial = source_hash(&package).unwrap();
+        fs::create_dir_all(temp.path().join("target/release")).unwrap();
+        fs::write(temp.path().join("target/release/tool"), "binary").unwrap();
+        fs::create_dir_all(temp.path().join(".git")).unwrap();
+        fs::write(temp.path().join(".git/HEAD"), "ref").unwrap();
+
+        assert_eq!(initial, source_hash(&package).unwrap());
+    }
+
+    #[test]
+    fn default_binary_prefers_single_bin_target() {
+        let temp = tempdir().unwrap();
+        let mut package = package_at(temp.path());
+        package.binaries = vec!["codegen".to_string()];
+        assert_eq!(package.default_binary(), "codegen");
+
+        package.binaries = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(package.default_binary(), "tool");
+    }
+}
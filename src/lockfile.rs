@@ -0,0 +1,217 @@
+use anyhow::{Context, Result, anyhow};
+use semver::Version;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, LockRules, ProjectConfig};
+
+/// A library version read from a project's `Cargo.lock`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedVersion {
+    pub lockfile: PathBuf,
+    pub version: Version,
+}
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: String,
+}
+
+/// The version of `library` locked by the nearest `Cargo.lock` at or above `dir`, or
+/// `None` when there is no lockfile or it does not mention `library`. A library
+/// locked at several versions is an error, since either could be the one to match.
+pub fn find_locked_version(dir: &Path, library: &str) -> Result<Option<LockedVersion>> {
+    let Some(lockfile) = dir
+        .ancestors()
+        .map(|ancestor| ancestor.join("Cargo.lock"))
+        .find(|path| path.is_file())
+    else {
+        return Ok(None);
+    };
+    let contents = fs::read_to_string(&lockfile)
+        .with_context(|| format!("failed to read {}", lockfile.display()))?;
+    let versions = locked_versions(&contents, library)
+        .with_context(|| format!("failed to parse {}", lockfile.display()))?;
+
+    match versions.as_slice() {
+        [] => Ok(None),
+        [version] => Ok(Some(LockedVersion {
+            version: version.clone(),
+            lockfile,
+        })),
+        several => {
+            let listed: Vec<String> = several.iter().map(Version::to_string).collect();
+            Err(anyhow!(
+                "{} locks several versions of {library} ({}); pin the tool with `@=<version>` instead",
+                lockfile.display(),
+                listed.join(", ")
+            ))
+        }
+    }
+}
+
+/// Like [`find_locked_version`] from the current directory, but a missing lockfile
+/// or library is an error: the spec `crate@lock:<library>` asked for it.
+pub fn locked_version(library: &str) -> Result<LockedVersion> {
+    let cwd = env::current_dir().context("failed to read the current directory")?;
+    find_locked_version(&cwd, library)?.ok_or_else(|| {
+        anyhow!(
+            "no Cargo.lock at or above {} locks {library}",
+            cwd.display()
+        )
+    })
+}
+
+/// The library whose locked version `binary` from `crate_name` follows, from a
+/// `[lock]` rule in the project's `cargox.toml` or, failing that, the cargox config.
+pub fn lock_rule(crate_name: &str, binary: &str) -> Result<Option<String>> {
+    let cwd = env::current_dir().context("failed to read the current directory")?;
+    let project = ProjectConfig::find(&cwd)?.map(|(_, project)| project.lock);
+    let global = Config::load()?.lock;
+    Ok(project
+        .into_iter()
+        .chain([global])
+        .find_map(|rules| matching_rule(rules, crate_name, binary)))
+}
+
+/// Rule keys are crate specs without a version. A `crate:bin` key only applies to
+/// that binary and wins over a plain `crate` key.
+fn matching_rule(rules: LockRules, crate_name: &str, binary: &str) -> Option<String> {
+    let mut general = None;
+    for (key, library) in rules {
+        let (name, key_binary) = match key.split_once(':') {
+            Some((name, key_binary)) => (name, Some(key_binary.trim())),
+            None => (key.as_str(), None),
+        };
+        if !same_crate(name.trim(), crate_name) {
+            continue;
+        }
+        match key_binary {
+            Some(key_binary) if key_binary == binary => return Some(library),
+            Some(_) => {}
+            None => general = Some(library),
+        }
+    }
+    general
+}
+
+/// The distinct versions of `package` in a lockfile, lowest first.
+fn locked_versions(contents: &str, package: &str) -> Result<Vec<Version>> {
+    let lockfile: Lockfile = toml::from_str(contents)?;
+    let mut versions = Vec::new();
+    for locked in lockfile.package {
+        if !same_crate(&locked.name, package) {
+            continue;
+        }
+        let version = Version::parse(&locked.version)
+            .with_context(|| format!("invalid version `{}` for {}", locked.version, locked.name))?;
+        if !versions.contains(&version) {
+            versions.push(version);
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+/// Crate names are unique regardless of `-` versus `_`.
+fn same_crate(a: &str, b: &str) -> bool {
+    a.replace('_', "-") == b.replace('_', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = r#"# This file is automatically @generated by Cargo.
+version = 4
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["wasm-bindgen"]
+
+[[package]]
+name = "wasm-bindgen"
+version = "0.2.92"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "4be2531df63900aeb2bca0daaaddec08491ee64ceecbee5076636a3b026795a8"
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "2.0.60"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    #[test]
+    fn locked_versions_reads_packages() {
+        assert_eq!(
+            locked_versions(LOCKFILE, "wasm-bindgen").unwrap(),
+            vec![Version::new(0, 2, 92)]
+        );
+        assert_eq!(
+            locked_versions(LOCKFILE, "wasm_bindgen").unwrap(),
+            vec![Version::new(0, 2, 92)]
+        );
+        assert_eq!(locked_versions(LOCKFILE, "syn").unwrap().len(), 2);
+        assert!(locked_versions(LOCKFILE, "serde").unwrap().is_empty());
+        assert!(locked_versions("[[package]]\nname = \"x\"\n", "x").is_err());
+    }
+
+    #[test]
+    fn matching_rule_accepts_binary_keys() {
+        let rules: LockRules = [
+            ("sqlx-cli", "sqlx-core"),
+            ("sqlx-cli:sqlx", "sqlx"),
+            ("wasm_bindgen_cli", "wasm-bindgen"),
+        ]
+        .into_iter()
+        .map(|(key, library)| (key.to_owned(), library.to_owned()))
+        .collect();
+
+        let rule = |crate_name, binary| matching_rule(rules.clone(), crate_name, binary);
+        assert_eq!(rule("sqlx-cli", "sqlx").as_deref(), Some("sqlx"));
+        assert_eq!(rule("sqlx-cli", "cargo-sqlx").as_deref(), Some("sqlx-core"));
+        assert_eq!(
+            rule("wasm-bindgen-cli", "wasm-bindgen").as_deref(),
+            Some("wasm-bindgen")
+        );
+        assert_eq!(rule("sqlx", "sqlx"), None);
+    }
+
+    #[test]
+    fn find_locked_version_uses_the_nearest_lockfile() {
+        let temp = tempfile::tempdir().unwrap();
+        let nested = temp.path().join("crates").join("web");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_locked_version(&nested, "wasm-bindgen").unwrap(), None);
+
+        let path = temp.path().join("Cargo.lock");
+        fs::write(&path, LOCKFILE).unwrap();
+        let locked = find_locked_version(&nested, "wasm-bindgen")
+            .unwrap()
+            .unwrap();
+        assert_eq!(locked.lockfile, path);
+        assert_eq!(locked.version, Version::new(0, 2, 92));
+        assert_eq!(find_locked_version(&nested, "serde").unwrap(), None);
+
+        let err = find_locked_version(&nested, "syn").unwrap_err().to_string();
+        assert!(
+            err.contains("several versions of syn (1.0.109, 2.0.60)"),
+            "{err}"
+        );
+    }
+}
@@ -1,29 +1,77 @@
+mod advisories;
+mod build_cache;
+mod cargo_config;
 mod cli;
+mod config;
+mod elf;
 mod executor;
+mod git;
 mod installer;
+mod local;
+mod lockfile;
+mod manifest;
 mod paths;
+mod policy;
 mod registry;
+mod reputation;
+mod smoke;
 mod target;
 #[cfg(test)]
 mod test_support;
+mod timestamp;
+mod toolchain;
 mod versions;
 
+use std::env;
 use std::path::PathBuf;
 use std::process::{ExitStatus, exit};
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 use semver::{Version, VersionReq};
 
+use advisories::check_advisories;
+use build_cache::run_cache_command;
 use cli::Cli;
-use executor::execute_binary;
-use installer::ensure_installed;
-use registry::{fetch_highest_matching_version, fetch_latest_version};
-use target::{Target, VersionSpec, parse_spec};
-use versions::{find_installed_version, latest_installed, versioned_binary_path};
+use config::Config;
+use executor::{Invocation, execute_binary, exit_code, verify_checksum};
+use git::resolve_commit;
+use installer::{
+    ensure_git_installed, ensure_installed, ensure_path_installed, will_build_from_source,
+};
+use local::{LocalPackage, inspect_package, source_hash};
+use lockfile::{find_locked_version, lock_rule, locked_version};
+use policy::Policy;
+use registry::{
+    VersionFilter, exact_requirement, fetch_highest_matching_version, fetch_latest_version,
+    is_exact_pin, is_yanked, known_crate_versions, select_version,
+};
+use target::{
+    CrateSpec, GitSource, PathSource, Source, Target, VersionSpec, parse_git_spec, parse_path_spec,
+    parse_spec,
+};
+use toolchain::{host_triple, rustc_version};
+use versions::{
+    find_installed_version, git_binary_path, latest_installed, list_stored_binaries,
+    path_binary_path, versioned_binary_path,
+};
 
 enum RunPlan {
-    UseInstalled { path: PathBuf },
-    InstallAndRun { version: Version },
+    /// `version` is known for registry installs only
+    UseInstalled {
+        path: PathBuf,
+        version: Option<Version>,
+    },
+    InstallAndRun {
+        version: Version,
+    },
+    BuildGitAndRun {
+        git: GitSource,
+        commit: String,
+    },
+    BuildPathAndRun {
+        package: LocalPackage,
+        hash: String,
+    },
 }
 
 fn main() {
@@ -35,10 +83,23 @@ fn main() {
 
 fn run_application() -> Result<ExitStatus> {
     let cli = parse_arguments()?;
+    if let Some(action) = cli.cache {
+        run_cache_command(action)?;
+        return Ok(ExitStatus::default());
+    }
+    if cli.list {
+        list_installed()?;
+        return Ok(ExitStatus::default());
+    }
+
     let target = parse_target_from_cli(&cli)?;
+    let policy = Policy::load()?;
+    if target.source == Source::Registry {
+        policy.check_denied(&target.crate_name)?;
+    }
 
     let plan = resolve_run_plan(&target, &cli)?;
-    execute_plan(&plan, &target, &cli)
+    execute_plan(&plan, &target, &cli, &policy)
 }
 
 fn parse_arguments() -> Result<Cli> {
@@ -46,40 +107,200 @@ fn parse_arguments() -> Result<Cli> {
 }
 
 fn parse_target_from_cli(cli: &Cli) -> Result<Target> {
-    let (crate_name, version) = parse_spec(&cli.crate_spec)?;
-    let binary = cli.bin.clone().unwrap_or_else(|| crate_name.clone());
+    let path_source = match (&cli.path, cli.crate_spec.as_deref()) {
+        (Some(path), package) => Some(PathSource {
+            path: path.clone(),
+            package: package.map(str::to_owned),
+        }),
+        (None, Some(spec)) => parse_path_spec(spec).map(|path| PathSource {
+            path,
+            package: None,
+        }),
+        (None, None) => None,
+    };
+    if let Some(path_source) = path_source {
+        reject_as_of(cli, "a local path")?;
+        return parse_path_target(cli, path_source);
+    }
+
+    let spec = cli
+        .crate_spec
+        .as_deref()
+        .expect("clap requires a crate spec unless --path or --cache is given");
+
+    if let Some(git) = parse_git_spec(spec)? {
+        reject_as_of(cli, "a git repository")?;
+        let crate_name = git
+            .package
+            .clone()
+            .unwrap_or_else(|| git.default_name().to_owned());
+        let explicit_binary = select_binary(spec, git.binary.clone(), cli)?;
+
+        return Ok(Target {
+            binary: explicit_binary
+                .clone()
+                .unwrap_or_else(|| crate_name.clone()),
+            explicit_binary: explicit_binary.is_some(),
+            crate_name,
+            version: VersionSpec::Unspecified,
+            source: Source::Git(git),
+            platform: target_platform(cli)?,
+            published_before: None,
+        });
+    }
+
+    let CrateSpec {
+        name: crate_name,
+        binary,
+        mut version,
+        published_before,
+    } = parse_spec(spec)?;
+    let explicit_binary = select_binary(spec, binary, cli)?;
+    let binary = explicit_binary
+        .clone()
+        .unwrap_or_else(|| crate_name.clone());
+    // With both a `<date` bound and --as-of, the earlier one wins
+    let published_before = published_before.into_iter().chain(cli.as_of).min();
+    if let VersionSpec::Lock(library) = &version
+        && published_before.is_some()
+    {
+        return Err(anyhow!(
+            "`{spec}` follows the version of {library} in Cargo.lock, so it cannot be combined with --as-of"
+        ));
+    }
+    // A `[lock]` rule only applies inside a project that locks the library, and never
+    // overrides a date bound given for this run
+    if matches!(version, VersionSpec::Unspecified)
+        && published_before.is_none()
+        && let Some(library) = lock_rule(&crate_name, &binary)?
+    {
+        let cwd = env::current_dir().context("failed to read the current directory")?;
+        if find_locked_version(&cwd, &library)?.is_some() {
+            version = VersionSpec::Lock(library);
+        }
+    }
 
     Ok(Target {
         crate_name,
         version,
         binary,
+        explicit_binary: explicit_binary.is_some(),
+        source: Source::Registry,
+        platform: target_platform(cli)?,
+        published_before,
+    })
+}
+
+/// Publish dates only exist for registry releases.
+fn reject_as_of(cli: &Cli, source: &str) -> Result<()> {
+    if cli.as_of.is_some() {
+        return Err(anyhow!(
+            "--as-of picks registry releases by publish date, but the crate comes from {source}"
+        ));
+    }
+    Ok(())
+}
+
+/// The binary named in the spec or with `--bin`, which must agree when both are given.
+fn select_binary(spec: &str, from_spec: Option<String>, cli: &Cli) -> Result<Option<String>> {
+    match (from_spec, &cli.bin) {
+        (Some(binary), Some(flag)) if binary != *flag => Err(anyhow!(
+            "`{spec}` selects the binary `{binary}`, but --bin asks for `{flag}`"
+        )),
+        (Some(binary), _) => Ok(Some(binary)),
+        (None, flag) => Ok(flag.clone()),
+    }
+}
+
+fn parse_path_target(cli: &Cli, source: PathSource) -> Result<Target> {
+    let package = inspect_package(&source)?;
+    let binary = cli
+        .bin
+        .clone()
+        .unwrap_or_else(|| package.default_binary().to_owned());
+
+    Ok(Target {
+        crate_name: package.name.clone(),
+        version: VersionSpec::Unspecified,
+        binary,
+        explicit_binary: cli.bin.is_some(),
+        source: Source::Path(package),
+        platform: target_platform(cli)?,
+        published_before: None,
     })
 }
 
+/// `--target` only matters when it names a triple other than the host's.
+fn target_platform(cli: &Cli) -> Result<Option<String>> {
+    let Some(triple) = &cli.target else {
+        return Ok(None);
+    };
+    if *triple == host_triple(cli.toolchain.as_deref())? {
+        return Ok(None);
+    }
+    Ok(Some(triple.clone()))
+}
+
 fn resolve_run_plan(target: &Target, cli: &Cli) -> Result<RunPlan> {
+    match &target.source {
+        Source::Registry => {}
+        Source::Git(git) => return resolve_git(target, cli, git),
+        Source::Path(package) => return resolve_path(target, cli, package),
+    }
+
+    if target.published_before.is_some() && !matches!(target.version, VersionSpec::Lock(_)) {
+        let requirement = match &target.version {
+            VersionSpec::Requirement(requirement) => Some(requirement),
+            _ => None,
+        };
+        return resolve_dated(target, cli, requirement);
+    }
+
     match &target.version {
         VersionSpec::Unspecified => resolve_unspecified(target, cli),
-        VersionSpec::Latest => resolve_latest(target, cli),
+        VersionSpec::Latest | VersionSpec::Next => resolve_latest(target, cli),
         VersionSpec::Requirement(requirement) => resolve_requirement(target, cli, requirement),
+        VersionSpec::Lock(library) => {
+            let locked = locked_version(library)?;
+            resolve_requirement(target, cli, &exact_requirement(&locked.version)).with_context(
+                || {
+                    format!(
+                        "{} must match {library} {}, as locked in {}",
+                        target.crate_name,
+                        locked.version,
+                        locked.lockfile.display()
+                    )
+                },
+            )
+        }
     }
 }
 
 fn resolve_unspecified(target: &Target, cli: &Cli) -> Result<RunPlan> {
     if !cli.force
-        && let Some(installed) = latest_installed(&target.binary)?
+        && let Some(installed) = latest_installed(
+            &target.binary,
+            target.platform.as_deref(),
+            include_pre(target, cli),
+        )?
     {
         return Ok(RunPlan::UseInstalled {
             path: installed.path,
+            version: Some(installed.version),
         });
     }
 
-    let version = fetch_latest_version(&target.crate_name)?;
+    let version = fetch_latest_version(&target.crate_name, &version_filter(target, cli)?)?;
     Ok(RunPlan::InstallAndRun { version })
 }
 
 fn resolve_latest(target: &Target, cli: &Cli) -> Result<RunPlan> {
-    let installed = latest_installed(&target.binary)?;
-    let remote = fetch_latest_version(&target.crate_name)?;
+    let installed = latest_installed(
+        &target.binary,
+        target.platform.as_deref(),
+        include_pre(target, cli),
+    )?;
+    let remote = fetch_latest_version(&target.crate_name, &version_filter(target, cli)?)?;
 
     if cli.force {
         return Ok(RunPlan::InstallAndRun { version: remote });
@@ -90,6 +311,7 @@ fn resolve_latest(target: &Target, cli: &Cli) -> Result<RunPlan> {
     {
         return Ok(RunPlan::UseInstalled {
             path: installed.path,
+            version: Some(installed.version),
         });
     }
 
@@ -98,35 +320,243 @@ fn resolve_latest(target: &Target, cli: &Cli) -> Result<RunPlan> {
 
 fn resolve_requirement(target: &Target, cli: &Cli, requirement: &VersionReq) -> Result<RunPlan> {
     if !cli.force
-        && let Some(installed) = find_installed_version(&target.binary, requirement)?
+        && let Some(installed) = find_installed_version(
+            &target.binary,
+            requirement,
+            target.platform.as_deref(),
+            include_pre(target, cli),
+        )?
     {
         return Ok(RunPlan::UseInstalled {
             path: installed.path,
+            version: Some(installed.version),
         });
     }
 
-    let version = fetch_highest_matching_version(&target.crate_name, Some(requirement))?;
+    let version = fetch_highest_matching_version(
+        &target.crate_name,
+        Some(requirement),
+        &version_filter(target, cli)?,
+    )?;
+    Ok(RunPlan::InstallAndRun { version })
+}
+
+/// A date bound is resolved against the registry first, since the newest installed
+/// version may postdate it; an installed copy of the chosen release is then reused.
+fn resolve_dated(target: &Target, cli: &Cli, requirement: Option<&VersionReq>) -> Result<RunPlan> {
+    let version = fetch_highest_matching_version(
+        &target.crate_name,
+        requirement,
+        &version_filter(target, cli)?,
+    )?;
+    if !cli.force
+        && let Some(installed) = find_installed_version(
+            &target.binary,
+            &exact_requirement(&version),
+            target.platform.as_deref(),
+            include_pre(target, cli),
+        )?
+    {
+        return Ok(RunPlan::UseInstalled {
+            path: installed.path,
+            version: Some(installed.version),
+        });
+    }
     Ok(RunPlan::InstallAndRun { version })
 }
 
-fn execute_plan(plan: &RunPlan, target: &Target, cli: &Cli) -> Result<ExitStatus> {
-    match plan {
-        RunPlan::UseInstalled { path } => execute_binary(path, &cli.args),
+/// Only consider releases the local compiler can build when we are going to build
+/// from source, or when asked to, and skip releases still in quarantine or published
+/// after a date bound. Prereleases are only considered for `--pre` or `@next`.
+fn version_filter(target: &Target, cli: &Cli) -> Result<VersionFilter> {
+    let rustc = if cli.respect_rust_version || will_build_from_source(cli) {
+        Some(rustc_version(cli.toolchain.as_deref())?)
+    } else {
+        None
+    };
+    Ok(VersionFilter {
+        rustc,
+        min_release_age: Config::load()?.install.min_release_age()?,
+        now: timestamp::now(),
+        pre: include_pre(target, cli),
+        published_before: target.published_before,
+    })
+}
+
+fn include_pre(target: &Target, cli: &Cli) -> bool {
+    cli.pre || matches!(target.version, VersionSpec::Next)
+}
+
+fn resolve_git(target: &Target, cli: &Cli, git: &GitSource) -> Result<RunPlan> {
+    let commit = resolve_commit(git)?;
+    let path = git_binary_path(&target.binary, &commit, target.platform.as_deref())?;
+
+    if !cli.force && path.is_file() {
+        return Ok(RunPlan::UseInstalled {
+            path,
+            version: None,
+        });
+    }
+
+    Ok(RunPlan::BuildGitAndRun {
+        git: git.clone(),
+        commit,
+    })
+}
+
+fn resolve_path(target: &Target, cli: &Cli, package: &LocalPackage) -> Result<RunPlan> {
+    let hash = source_hash(package)?;
+    let path = path_binary_path(&target.binary, &hash, target.platform.as_deref())?;
+
+    if !cli.force && path.is_file() {
+        return Ok(RunPlan::UseInstalled {
+            path,
+            version: None,
+        });
+    }
+
+    Ok(RunPlan::BuildPathAndRun {
+        package: package.clone(),
+        hash,
+    })
+}
+
+fn execute_plan(plan: &RunPlan, target: &Target, cli: &Cli, policy: &Policy) -> Result<ExitStatus> {
+    let platform = target.platform.as_deref();
+    if target.source == Source::Registry
+        && let RunPlan::UseInstalled {
+            version: Some(version),
+            ..
+        }
+        | RunPlan::InstallAndRun { version } = plan
+    {
+        // The plan already holds the locked version, so a lock is an exact pin of it
+        let requirement = match &target.version {
+            VersionSpec::Requirement(requirement) => Some(requirement.clone()),
+            VersionSpec::Lock(_) => Some(exact_requirement(version)),
+            VersionSpec::Unspecified | VersionSpec::Latest | VersionSpec::Next => None,
+        };
+        check_advisories(&target.crate_name, version, requirement.as_ref())?;
+        if matches!(plan, RunPlan::UseInstalled { .. }) {
+            check_yanked(&target.crate_name, version, requirement.as_ref())?;
+        }
+    }
+
+    let binary_path = match plan {
+        RunPlan::UseInstalled { path, .. } => path.clone(),
         RunPlan::InstallAndRun { version } => {
+            policy.confirm_install(&target.crate_name, version, cli)?;
             ensure_installed(target, cli, version)?;
-            let binary_path = versioned_binary_path(&target.binary, version)?;
-            execute_binary(&binary_path, &cli.args)
+            versioned_binary_path(&target.binary, version, platform)?
+        }
+        RunPlan::BuildGitAndRun { git, commit } => {
+            ensure_git_installed(target, cli, git, commit)?;
+            git_binary_path(&target.binary, commit, platform)?
+        }
+        RunPlan::BuildPathAndRun { package, hash } => {
+            ensure_path_installed(target, cli, package, hash)?;
+            path_binary_path(&target.binary, hash, platform)?
         }
+    };
+
+    if let Some(triple) = platform
+        && !cli.allow_foreign
+    {
+        return Err(anyhow!(
+            "{} is built for {triple}, which does not match this host; \
+             pass --allow-foreign to run it anyway",
+            binary_path.display()
+        ));
+    }
+
+    let verify = Config::load()?.execute.verify_checksum;
+    if cli.paranoid || verify {
+        verify_checksum(&binary_path, cli.paranoid).map_err(|err| {
+            err.context(format!(
+                "reinstall it with `cargox --force {}`",
+                reinstall_spec(cli)
+            ))
+        })?;
     }
+
+    execute_binary(&binary_path, &Invocation::new(&target.binary, &cli.args))
 }
 
-fn exit_with_status(status: ExitStatus) -> ! {
-    if let Some(code) = status.code() {
-        exit(code);
-    } else {
-        eprintln!("process terminated by signal");
-        exit(1);
+/// Warn about, or with `[execute] refuse-yanked` refuse, an installed version that
+/// has been yanked since; exact pins are only warned about. Only registry metadata
+/// already at hand is consulted, so this never delays a run with a network request.
+fn check_yanked(
+    crate_name: &str,
+    version: &Version,
+    requirement: Option<&VersionReq>,
+) -> Result<()> {
+    let Some(versions) = known_crate_versions(crate_name)? else {
+        return Ok(());
+    };
+    if !is_yanked(&versions, version) {
+        return Ok(());
     }
+
+    let matching = requirement
+        .map(|req| format!(" matching {req}"))
+        .unwrap_or_default();
+    let advice = match select_version(
+        crate_name,
+        &versions,
+        requirement,
+        &VersionFilter::default(),
+    ) {
+        Ok(selection) => format!(
+            "{crate_name} {} is the newest release{matching}; run `cargox {crate_name}@={}`",
+            selection.version, selection.version
+        ),
+        Err(_) => format!("no release of {crate_name}{matching} remains"),
+    };
+
+    // An exact pin asks for this release knowingly, as a lockfile entry would
+    if Config::load()?.execute.refuse_yanked && !requirement.is_some_and(is_exact_pin) {
+        return Err(anyhow!(
+            "refusing to run {crate_name} {version}: it has been yanked; {advice}"
+        ));
+    }
+    eprintln!("warning: {crate_name} {version} has been yanked; {advice}");
+    Ok(())
+}
+
+fn reinstall_spec(cli: &Cli) -> String {
+    match (&cli.path, &cli.crate_spec) {
+        (Some(path), Some(spec)) => format!("--path {} {spec}", path.display()),
+        (Some(path), None) => format!("--path {}", path.display()),
+        (None, spec) => spec.clone().unwrap_or_default(),
+    }
+}
+
+/// Print every stored binary with the crate it came from and its target triple.
+fn list_installed() -> Result<()> {
+    let records = manifest::records()?;
+    for stored in list_stored_binaries()? {
+        let record = records.get(&stored.name);
+        let origin = match record {
+            Some(record) if record.source == "registry" => format!(
+                "{}@{}",
+                record.crate_name,
+                record.version.as_deref().unwrap_or("?")
+            ),
+            Some(record) => format!("{} ({})", record.crate_name, record.source),
+            None => "unknown".to_string(),
+        };
+        let triple = stored
+            .platform
+            .as_deref()
+            .or_else(|| record.and_then(|record| record.target.as_deref()))
+            .unwrap_or("host");
+        println!("{}\t{origin}\t{triple}", stored.path.display());
+    }
+    Ok(())
+}
+
+fn exit_with_status(status: ExitStatus) -> ! {
+    exit(exit_code(status));
 }
 
 fn exit_with_error(err: anyhow::Error) -> ! {
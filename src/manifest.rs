@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::paths::get_install_dir;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Metadata recorded for every binary in the store, keyed by its file name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallRecord {
+    pub crate_name: String,
+    /// `registry`, `git+<url>#<commit>` or `path+<dir>`
+    pub source: String,
+    pub installer: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rustc: Option<String>,
+    /// Target triple for binaries installed with `--target`; absent for host binaries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// `gnu`, `musl` or `static` for ELF binaries, judged by their dynamic loader
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub libc: Option<String>,
+    /// SHA-256 of the binary as installed, checked before running it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    binaries: BTreeMap<String, InstallRecord>,
+}
+
+pub fn record_install(binary_path: &Path, record: InstallRecord) -> Result<()> {
+    let manifest_path = manifest_path()?;
+    let mut manifest = load(&manifest_path)?;
+    manifest.binaries.insert(key(binary_path)?, record);
+    save(&manifest_path, &manifest)
+}
+
+pub fn lookup(binary_path: &Path) -> Result<Option<InstallRecord>> {
+    Ok(load(&manifest_path()?)?.binaries.remove(&key(binary_path)?))
+}
+
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Forget a binary, e.g. after rolling back a broken install.
+pub fn remove_install(binary_path: &Path) -> Result<()> {
+    let manifest_path = manifest_path()?;
+    let mut manifest = load(&manifest_path)?;
+    if manifest.binaries.remove(&key(binary_path)?).is_some() {
+        save(&manifest_path, &manifest)?;
+    }
+    Ok(())
+}
+
+/// All recorded installs, keyed by their path relative to `bin/`.
+pub fn records() -> Result<BTreeMap<String, InstallRecord>> {
+    Ok(load(&manifest_path()?)?.binaries)
+}
+
+fn manifest_path() -> Result<PathBuf> {
+    Ok(get_install_dir()?.join(MANIFEST_FILE))
+}
+
+/// Host binaries are keyed by file name and other targets by `<triple>/<file name>`.
+fn key(binary_path: &Path) -> Result<String> {
+    let bin_dir = get_install_dir()?.join("bin");
+    let relative = binary_path.strip_prefix(&bin_dir).unwrap_or(binary_path);
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    Ok(match parts.as_slice() {
+        [.., platform, name] if relative != binary_path => format!("{platform}/{name}"),
+        _ => binary_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    })
+}
+
+fn load(path: &Path) -> Result<Manifest> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse install manifest {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::default()),
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to read install manifest {}", path.display()))
+        }
+    }
+}
+
+/// Write through a temporary file so an interrupted write never leaves a
+/// truncated manifest behind.
+fn save(path: &Path, manifest: &Manifest) -> Result<()> {
+    let json = serde_json::to_vec_pretty(manifest).context("failed to serialize manifest")?;
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, json)
+        .with_context(|| format!("failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("failed to update install manifest {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::env_lock;
+    use std::env;
+
+    #[test]
+    fn file_sha256_hashes_contents() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("file");
+        fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            file_sha256(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn record_install_round_trips() {
+        let _guard = env_lock().lock().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        unsafe {
+            env::set_var("CARGOX_INSTALL_DIR", temp.path());
+        }
+
+        let binary = temp.path().join("bin").join("tool-1.0.0");
+        let record = InstallRecord {
+            crate_name: "tool".to_string(),
+            source: "registry".to_string(),
+            installer: "cargo install".to_string(),
+            version: Some("1.0.0".to_string()),
+            toolchain: Some("stable".to_string()),
+            rustc: Some("1.95.0".to_string()),
+            ..InstallRecord::default()
+        };
+
+        let manifest_path = manifest_path().unwrap();
+        assert!(load(&manifest_path).unwrap().binaries.is_empty());
+        record_install(&binary, record.clone()).unwrap();
+        let found = load(&manifest_path).unwrap().binaries.remove("tool-1.0.0");
+        let looked_up = lookup(&binary).unwrap();
+
+        let foreign = temp
+            .path()
+            .join("bin")
+            .join("aarch64-unknown-linux-gnu")
+            .join("tool-1.0.0");
+        let foreign_key = key(&foreign).unwrap();
+        remove_install(&binary).unwrap();
+        let removed = load(&manifest_path).unwrap().binaries.is_empty();
+
+        unsafe {
+            env::remove_var("CARGOX_INSTALL_DIR");
+        }
+
+        assert_eq!(found, Some(record));
+        assert_eq!(looked_up, found);
+        assert!(removed);
+        assert_eq!(foreign_key, "aarch64-unknown-linux-gnu/tool-1.0.0");
+    }
+}
@@ -2,7 +2,8 @@ use anyhow::{Context, Result, anyhow};
 use directories::ProjectDirs;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
 pub fn get_install_dir() -> Result<PathBuf> {
     // First check if user has explicitly set an install path
@@ -13,7 +14,7 @@ pub fn get_install_dir() -> Result<PathBuf> {
     // Use XDG data directory for Linux/Unix or equivalent on other platforms
     if let Some(proj_dirs) = ProjectDirs::from("", "", "cargox") {
         let data_dir = proj_dirs.data_dir();
-        fs::create_dir_all(data_dir)
+        create_store_dir(data_dir)
             .with_context(|| format!("failed to create data directory: {}", data_dir.display()))?;
         return Ok(data_dir.to_path_buf());
     }
@@ -21,7 +22,7 @@ pub fn get_install_dir() -> Result<PathBuf> {
     // Fallback to .local/share/cargox
     if let Some(home) = home_dir() {
         let fallback = home.join(".local").join("share").join("cargox");
-        fs::create_dir_all(&fallback).with_context(|| {
+        create_store_dir(&fallback).with_context(|| {
             format!(
                 "failed to create fallback directory: {}",
                 fallback.display()
@@ -33,6 +34,37 @@ pub fn get_install_dir() -> Result<PathBuf> {
     Err(anyhow!("unable to determine install directory"))
 }
 
+/// Create `path` and any missing parents. On Unix new directories get mode `0o755`
+/// whatever the umask, so other users can never swap binaries in the store.
+pub fn create_store_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(path)
+    }
+    #[cfg(not(unix))]
+    {
+        fs::create_dir_all(path)
+    }
+}
+
+/// Location of the cargox configuration file. `CARGOX_CONFIG` overrides the
+/// platform configuration directory.
+pub fn get_config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("CARGOX_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    if let Some(proj_dirs) = ProjectDirs::from("", "", "cargox") {
+        return Some(proj_dirs.config_dir().join("config.toml"));
+    }
+
+    home_dir().map(|home| home.join(".config").join("cargox").join("config.toml"))
+}
+
 fn home_dir() -> Option<PathBuf> {
     env::var_os("HOME")
         .or_else(|| env::var_os("USERPROFILE"))
@@ -0,0 +1,278 @@
+use anyhow::{Context, Result, anyhow};
+use semver::Version;
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::cli::Cli;
+use crate::config::{Config, ProjectConfig, TrustConfig, env_flag};
+use crate::manifest;
+use crate::paths::get_config_path;
+use crate::registry::{CrateInfo, fetch_crate_info};
+use crate::reputation;
+use crate::timestamp;
+
+/// Allow and deny rules for registry crates, gathered from the global config and
+/// the nearest `cargox.toml`.
+#[derive(Debug, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: String,
+    allow: bool,
+    /// File the rule was read from, for error messages
+    origin: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Decision<'a> {
+    Allowed,
+    Denied { pattern: &'a str, origin: &'a str },
+    Unknown,
+}
+
+impl Policy {
+    pub fn load() -> Result<Self> {
+        let mut policy = Self::default();
+        let config_origin = get_config_path()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "config.toml".to_string());
+        policy.add(&Config::load()?.trust, &config_origin);
+
+        let cwd = env::current_dir().context("failed to read the current directory")?;
+        if let Some((path, project)) = ProjectConfig::find(&cwd)? {
+            policy.add(&project.trust, &path.display().to_string());
+        }
+        Ok(policy)
+    }
+
+    fn add(&mut self, trust: &TrustConfig, origin: &str) {
+        let deny = trust.deny.iter().map(|pattern| (pattern, false));
+        let allow = trust.allow.iter().map(|pattern| (pattern, true));
+        for (pattern, allow) in deny.chain(allow) {
+            self.rules.push(Rule {
+                pattern: pattern.clone(),
+                allow,
+                origin: origin.to_string(),
+            });
+        }
+    }
+
+    /// Deny rules win over allow rules wherever either was written.
+    fn decision(&self, crate_name: &str) -> Decision<'_> {
+        let matching = |allow| {
+            self.rules
+                .iter()
+                .find(move |rule| rule.allow == allow && pattern_matches(&rule.pattern, crate_name))
+        };
+        if let Some(rule) = matching(false) {
+            return Decision::Denied {
+                pattern: &rule.pattern,
+                origin: &rule.origin,
+            };
+        }
+        if matching(true).is_some() {
+            return Decision::Allowed;
+        }
+        Decision::Unknown
+    }
+
+    /// Refuse to run a denied crate, even one that is already installed.
+    pub fn check_denied(&self, crate_name: &str) -> Result<()> {
+        match self.decision(crate_name) {
+            Decision::Denied { pattern, origin } => Err(anyhow!(
+                "refusing to run {crate_name}: it is denied by `{pattern}` in {origin}"
+            )),
+            Decision::Allowed | Decision::Unknown => Ok(()),
+        }
+    }
+
+    /// Trust on first use: crates that are allowed or were installed before go ahead,
+    /// anything else is checked for signs of typosquatting and then needs `--yes` or an
+    /// interactive confirmation. Without a terminal, or when `CI` is set, unknown
+    /// crates are refused.
+    pub fn confirm_install(&self, crate_name: &str, version: &Version, cli: &Cli) -> Result<()> {
+        self.check_denied(crate_name)?;
+        if self.decision(crate_name) == Decision::Allowed || installed_before(crate_name)? {
+            return Ok(());
+        }
+
+        let info = fetch_crate_info(crate_name);
+        let known = info.as_ref().ok().and_then(Option::as_ref);
+        for warning in reputation::warnings(crate_name, known, timestamp::now()) {
+            eprintln!("warning: {warning}");
+        }
+        if cli.yes {
+            return Ok(());
+        }
+
+        let in_ci = env_flag("CI").unwrap_or(false);
+        if in_ci || !io::stdin().is_terminal() {
+            return Err(anyhow!(
+                "refusing to install {crate_name}: it has not been installed before and {}; \
+                 add it to `[trust] allow` in {} or the cargox config, or pass --yes",
+                if in_ci {
+                    "CI is set"
+                } else {
+                    "there is no terminal to confirm it"
+                },
+                ProjectConfig::FILE_NAME
+            ));
+        }
+
+        eprintln!("{crate_name} has not been installed by cargox before.");
+        match &info {
+            Ok(Some(info)) => {
+                for line in describe(info) {
+                    eprintln!("  {line}");
+                }
+            }
+            Ok(None) => eprintln!("  (no crates.io metadata for the configured registry)"),
+            Err(err) => eprintln!("  (could not fetch crates.io metadata: {err:#})"),
+        }
+        eprint!("Install and run {crate_name} {version}? [y/N] ");
+        io::stderr().flush().ok();
+
+        let mut answer = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .context("failed to read confirmation")?;
+        if matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+            Ok(())
+        } else {
+            Err(anyhow!("installation of {crate_name} declined"))
+        }
+    }
+}
+
+/// Crates with a registry binary in the store have been trusted already.
+fn installed_before(crate_name: &str) -> Result<bool> {
+    Ok(manifest::records()?
+        .values()
+        .any(|record| record.source == "registry" && record.crate_name == crate_name))
+}
+
+/// Crate names are compared the way crates.io does: case-insensitively and with `-`
+/// and `_` interchangeable. A trailing `*` matches any suffix.
+fn pattern_matches(pattern: &str, crate_name: &str) -> bool {
+    let normalize = |name: &str| name.trim().to_ascii_lowercase().replace('_', "-");
+    let crate_name = normalize(crate_name);
+    match pattern.trim().strip_suffix('*') {
+        Some(prefix) => crate_name.starts_with(&normalize(prefix)),
+        None => crate_name == normalize(pattern),
+    }
+}
+
+fn describe(info: &CrateInfo) -> Vec<String> {
+    let owners = if info.owners.is_empty() {
+        "none listed".to_string()
+    } else {
+        info.owners.join(", ")
+    };
+    let downloads = match info.recent_downloads {
+        Some(recent) => format!(
+            "{} ({} in the last 90 days)",
+            format_count(info.downloads),
+            format_count(recent)
+        ),
+        None => format_count(info.downloads),
+    };
+    vec![
+        format!("owners:     {owners}"),
+        format!("downloads:  {downloads}"),
+        format!(
+            "repository: {}",
+            info.repository.as_deref().unwrap_or("none listed")
+        ),
+    ]
+}
+
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(global: TrustConfig, project: TrustConfig) -> Policy {
+        let mut policy = Policy::default();
+        policy.add(&global, "config.toml");
+        policy.add(&project, "cargox.toml");
+        policy
+    }
+
+    fn trust(allow: &[&str], deny: &[&str]) -> TrustConfig {
+        TrustConfig {
+            allow: allow.iter().map(|name| name.to_string()).collect(),
+            deny: deny.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn pattern_matches_names_and_prefixes() {
+        assert!(pattern_matches("ripgrep", "ripgrep"));
+        assert!(pattern_matches("cargo_nextest", "cargo-nextest"));
+        assert!(pattern_matches("Cargo-*", "cargo-deny"));
+        assert!(pattern_matches("*", "anything"));
+        assert!(!pattern_matches("ripgrep", "ripgrep-all"));
+        assert!(!pattern_matches("cargo-*", "cargox"));
+    }
+
+    #[test]
+    fn decision_prefers_deny_rules_from_any_file() {
+        let policy = policy(
+            trust(&["cargo-*"], &[]),
+            trust(&["ripgrep"], &["cargo-evil"]),
+        );
+        assert_eq!(policy.decision("ripgrep"), Decision::Allowed);
+        assert_eq!(policy.decision("cargo-deny"), Decision::Allowed);
+        assert_eq!(policy.decision("bat"), Decision::Unknown);
+        assert_eq!(
+            policy.decision("cargo_evil"),
+            Decision::Denied {
+                pattern: "cargo-evil",
+                origin: "cargox.toml"
+            }
+        );
+
+        let err = policy.check_denied("cargo-evil").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("denied by `cargo-evil` in cargox.toml")
+        );
+        assert!(policy.check_denied("bat").is_ok());
+    }
+
+    #[test]
+    fn describe_reports_owners_downloads_and_repository() {
+        let info = CrateInfo {
+            downloads: 12_345_678,
+            recent_downloads: Some(999),
+            repository: Some("https://github.com/BurntSushi/ripgrep".to_string()),
+            owners: vec!["BurntSushi".to_string()],
+            ..CrateInfo::default()
+        };
+        assert_eq!(
+            describe(&info),
+            vec![
+                "owners:     BurntSushi",
+                "downloads:  12,345,678 (999 in the last 90 days)",
+                "repository: https://github.com/BurntSushi/ripgrep",
+            ]
+        );
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(1_000), "1,000");
+    }
+}
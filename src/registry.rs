@@ -1,25 +1,229 @@
 use anyhow::{Context, Result, anyhow};
+use flate2::read::GzDecoder;
 use reqwest::blocking::Client;
 use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
-use semver::{Version, VersionReq};
-use serde::Deserialize;
+use semver::{Comparator, Op, Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 
+use crate::cargo_config::{CargoConfig, CratesIoSource, crates_io_replacement};
+use crate::paths::{create_store_dir, get_install_dir};
+use crate::timestamp::{self, SECS_PER_DAY, format_instant, parse_rfc3339};
+use crate::toolchain::parse_rust_version;
+use crate::versions::version_matches;
+
 #[derive(Deserialize)]
 struct VersionsResponse {
     versions: Vec<CrateVersion>,
 }
 
+/// A published release. Also deserializes registry index entries, which name the
+/// version field `vers`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateVersion {
+    #[serde(alias = "vers")]
+    pub num: String,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default)]
+    pub rust_version: Option<String>,
+    /// RFC 3339 publish time, called `pubtime` in registry index entries
+    #[serde(default, alias = "pubtime")]
+    pub created_at: Option<String>,
+    /// SHA-256 of the `.crate` file, called `cksum` in registry index entries
+    #[serde(default, alias = "cksum")]
+    pub checksum: Option<String>,
+}
+
+/// What crates.io knows about a crate's popularity and provenance.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrateInfo {
+    pub downloads: u64,
+    pub recent_downloads: Option<u64>,
+    pub repository: Option<String>,
+    /// RFC 3339 time the first version was published
+    pub created_at: Option<String>,
+    /// Logins of the users and teams that can publish the crate
+    pub owners: Vec<String>,
+}
+
 #[derive(Deserialize)]
-struct CrateVersion {
-    num: String,
-    yanked: bool,
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateData,
 }
 
-pub fn fetch_highest_matching_version(
-    crate_name: &str,
-    requirement: Option<&VersionReq>,
-) -> Result<Version> {
+#[derive(Deserialize)]
+struct CrateData {
+    downloads: u64,
+    #[serde(default)]
+    recent_downloads: Option<u64>,
+    #[serde(default)]
+    repository: Option<String>,
+    #[serde(default)]
+    created_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    login: String,
+}
+
+/// How long versions saved by an earlier run are trusted for yank checks.
+const CACHED_VERSIONS_MAX_AGE: i64 = SECS_PER_DAY;
+
+/// Versions as last fetched, kept on disk so later runs can check installed
+/// versions without a network request.
+#[derive(Serialize, Deserialize)]
+struct CachedVersions {
+    fetched_at: i64,
+    versions: Vec<CrateVersion>,
+}
+
+fn memoized_versions() -> &'static Mutex<HashMap<String, Vec<CrateVersion>>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Vec<CrateVersion>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Fetch the published versions of a crate. Responses are memoized for the lifetime
+/// of the process so that resolution and installation share a single request, and
+/// saved under `cache/registry/` for [`known_crate_versions`].
+pub fn fetch_crate_versions(crate_name: &str) -> Result<Vec<CrateVersion>> {
+    if let Some(versions) = memoized_versions().lock().unwrap().get(crate_name) {
+        return Ok(versions.clone());
+    }
+
+    let versions = match crates_io_replacement() {
+        Some(CratesIoSource::Directory(dir)) => read_directory_versions(&dir, crate_name)?,
+        Some(CratesIoSource::LocalRegistry(dir)) => {
+            let path = dir.join("index").join(index_path(crate_name));
+            let contents = fs::read_to_string(&path).with_context(|| {
+                format!(
+                    "{crate_name} not found in local registry at {}",
+                    dir.display()
+                )
+            })?;
+            parse_index_entries(&contents)?
+        }
+        Some(CratesIoSource::Registry(index)) if index.starts_with("sparse+") => {
+            request_sparse_versions(&index["sparse+".len()..], crate_name)?
+        }
+        Some(CratesIoSource::Registry(index)) => {
+            return Err(anyhow!(
+                "crates.io is replaced with the git index {index}, which is unsupported for \
+                 version lookup; use its sparse index (`sparse+https://...`) or a vendored \
+                 directory instead"
+            ));
+        }
+        None => request_crate_versions(crate_name)?,
+    };
+    if let Err(err) = save_cached_versions(crate_name, &versions) {
+        eprintln!("warning: failed to cache registry metadata for {crate_name}: {err:#}");
+    }
+    memoized_versions()
+        .lock()
+        .unwrap()
+        .insert(crate_name.to_owned(), versions.clone());
+    Ok(versions)
+}
+
+/// Versions fetched earlier in this run, or saved by a run within the last day.
+/// Never makes a request, so it is cheap enough to call before every execution.
+pub fn known_crate_versions(crate_name: &str) -> Result<Option<Vec<CrateVersion>>> {
+    if let Some(versions) = memoized_versions().lock().unwrap().get(crate_name) {
+        return Ok(Some(versions.clone()));
+    }
+
+    let path = versions_cache_path(crate_name)?;
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    // A corrupt cache entry is as good as a missing one
+    let Ok(cached) = serde_json::from_slice::<CachedVersions>(&bytes) else {
+        return Ok(None);
+    };
+    let age = timestamp::now() - cached.fetched_at;
+    Ok((0..=CACHED_VERSIONS_MAX_AGE)
+        .contains(&age)
+        .then_some(cached.versions))
+}
+
+fn versions_cache_path(crate_name: &str) -> Result<PathBuf> {
+    Ok(get_install_dir()?
+        .join("cache")
+        .join("registry")
+        .join(format!("{}.json", crate_name.to_ascii_lowercase())))
+}
+
+fn save_cached_versions(crate_name: &str, versions: &[CrateVersion]) -> Result<()> {
+    let path = versions_cache_path(crate_name)?;
+    let dir = path.parent().expect("cache path has a parent");
+    create_store_dir(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let cached = CachedVersions {
+        fetched_at: timestamp::now(),
+        versions: versions.to_vec(),
+    };
+    let json = serde_json::to_vec(&cached).context("failed to serialize registry metadata")?;
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, json)
+        .with_context(|| format!("failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, &path).with_context(|| format!("failed to update {}", path.display()))
+}
+
+/// Download counts, repository and owners from the crates.io API. Returns `None` when
+/// crates.io has been replaced by a mirror or vendored sources, which carry no such
+/// metadata and must not be bypassed.
+pub fn fetch_crate_info(crate_name: &str) -> Result<Option<CrateInfo>> {
+    if crates_io_replacement().is_some() {
+        return Ok(None);
+    }
+
+    let (client, headers) = http_client()?;
+    let base = format!("https://crates.io/api/v1/crates/{crate_name}");
+
+    let data: CrateResponse = client
+        .get(&base)
+        .headers(headers.clone())
+        .send()
+        .context("failed to contact crates.io")?
+        .error_for_status()
+        .context("crates.io returned an error status")?
+        .json()
+        .context("failed to parse crates.io response")?;
+    let owners: OwnersResponse = client
+        .get(format!("{base}/owners"))
+        .headers(headers)
+        .send()
+        .context("failed to contact crates.io")?
+        .error_for_status()
+        .context("crates.io returned an error status")?
+        .json()
+        .context("failed to parse crates.io owners response")?;
+
+    Ok(Some(CrateInfo {
+        downloads: data.krate.downloads,
+        recent_downloads: data.krate.recent_downloads,
+        repository: data.krate.repository,
+        created_at: data.krate.created_at,
+        owners: owners.users.into_iter().map(|owner| owner.login).collect(),
+    }))
+}
+
+fn http_client() -> Result<(Client, HeaderMap)> {
     let client = Client::builder()
         .timeout(Duration::from_secs(10))
         .build()
@@ -32,6 +236,11 @@ pub fn fetch_highest_matching_version(
         HeaderValue::from_str(&user_agent).context("invalid user agent")?,
     );
 
+    Ok((client, headers))
+}
+
+fn request_crate_versions(crate_name: &str) -> Result<Vec<CrateVersion>> {
+    let (client, headers) = http_client()?;
     let url = format!("https://crates.io/api/v1/crates/{crate_name}");
 
     let response = client
@@ -46,34 +255,709 @@ pub fn fetch_highest_matching_version(
         .json()
         .context("failed to parse crates.io response")?;
 
-    let mut versions: Vec<Version> = payload
-        .versions
-        .into_iter()
-        .filter(|v| !v.yanked)
-        .filter_map(|entry| Version::parse(&entry.num).ok())
-        .collect();
+    Ok(payload.versions)
+}
+
+/// Read versions from a sparse registry index that replaces crates.io.
+fn request_sparse_versions(index: &str, crate_name: &str) -> Result<Vec<CrateVersion>> {
+    let (client, headers) = http_client()?;
+    let url = format!(
+        "{}/{}",
+        index.trim_end_matches('/'),
+        index_path(crate_name).to_string_lossy().replace('\\', "/")
+    );
+
+    let body = client
+        .get(&url)
+        .headers(headers)
+        .send()
+        .with_context(|| format!("failed to contact registry index {index}"))?
+        .error_for_status()
+        .with_context(|| format!("registry index returned an error status for {crate_name}"))?
+        .text()
+        .context("failed to read registry index response")?;
+
+    parse_index_entries(&body)
+}
+
+/// Registry index files hold one JSON object per line, one per published version.
+fn parse_index_entries(contents: &str) -> Result<Vec<CrateVersion>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("failed to parse registry index entry"))
+        .collect()
+}
+
+/// Location of a crate's file within a registry index, e.g. `3/s/syn` or `se/rd/serde`.
+fn index_path(crate_name: &str) -> PathBuf {
+    let name = crate_name.to_ascii_lowercase();
+    match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+/// List the versions of a crate present in a `cargo vendor` directory source.
+/// Vendored crates are never yanked, since only what the lockfile needs is kept.
+fn read_directory_versions(dir: &Path, crate_name: &str) -> Result<Vec<CrateVersion>> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read vendor directory {}", dir.display()))?;
+
+    let mut versions = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+        let manifest = entry.path().join("Cargo.toml");
+        let Ok(contents) = fs::read_to_string(&manifest) else {
+            continue;
+        };
+        let Ok(parsed) = contents.parse::<toml::Table>() else {
+            continue;
+        };
+        let Some(package) = parsed.get("package").and_then(toml::Value::as_table) else {
+            continue;
+        };
+        let field = |key: &str| package.get(key).and_then(toml::Value::as_str);
+        if field("name") != Some(crate_name) {
+            continue;
+        }
+        if let Some(num) = field("version") {
+            versions.push(CrateVersion {
+                num: num.to_owned(),
+                yanked: false,
+                rust_version: field("rust-version").map(str::to_owned),
+                created_at: None,
+                checksum: None,
+            });
+        }
+    }
 
     if versions.is_empty() {
+        return Err(anyhow!(
+            "{crate_name} not found in vendored sources at {}",
+            dir.display()
+        ));
+    }
+    Ok(versions)
+}
+
+/// Constraints applied to published versions on top of the user's requirement.
+#[derive(Debug, Clone, Default)]
+pub struct VersionFilter {
+    /// Skip releases whose `rust-version` is newer than this compiler
+    pub rustc: Option<Version>,
+    /// Skip releases published fewer than this many seconds before `now`, unless
+    /// the requirement pins an exact version
+    pub min_release_age: Option<u64>,
+    /// Unix time that release ages are measured against
+    pub now: i64,
+    /// Consider prereleases, which are otherwise only chosen when the requirement
+    /// names one
+    pub pre: bool,
+    /// Only consider releases published before this Unix time; releases without a
+    /// publish time are skipped too, since they cannot be placed
+    pub published_before: Option<i64>,
+}
+
+/// The version chosen by [`select_version`], with explanations for any newer
+/// matching releases that the filter skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub version: Version,
+    pub notes: Vec<String>,
+}
+
+pub fn fetch_highest_matching_version(
+    crate_name: &str,
+    requirement: Option<&VersionReq>,
+    filter: &VersionFilter,
+) -> Result<Version> {
+    let versions = fetch_crate_versions(crate_name)?;
+    let selection = select_version(crate_name, &versions, requirement, filter)?;
+    for note in &selection.notes {
+        eprintln!("{note}");
+    }
+    Ok(selection.version)
+}
+
+pub fn fetch_latest_version(crate_name: &str, filter: &VersionFilter) -> Result<Version> {
+    fetch_highest_matching_version(crate_name, None, filter)
+}
+
+pub fn select_version(
+    crate_name: &str,
+    versions: &[CrateVersion],
+    requirement: Option<&VersionReq>,
+    filter: &VersionFilter,
+) -> Result<Selection> {
+    // Like a lockfile entry, an exact pin may name a yanked release; nothing else can
+    let pinned = requirement.is_some_and(is_exact_pin);
+    let mut candidates: Vec<(Version, &CrateVersion)> = versions
+        .iter()
+        .filter(|v| pinned || !v.yanked)
+        .filter_map(|entry| Version::parse(&entry.num).ok().map(|num| (num, entry)))
+        .collect();
+
+    if candidates.is_empty() {
         return Err(anyhow!("no published versions found for {crate_name}"));
     }
 
-    versions.sort();
+    candidates.sort_by(|a, b| a.0.cmp(&b.0));
 
     if let Some(req) = requirement {
-        if let Some(version) = versions.iter().rev().find(|v| req.matches(v)) {
-            return Ok(version.clone());
+        candidates.retain(|(version, _)| version_matches(req, version, filter.pre));
+        if candidates.is_empty() {
+            return Err(anyhow!(
+                "no published versions of {crate_name} satisfy requirement {req}"
+            ));
         }
-        return Err(anyhow!(
-            "no published versions of {crate_name} satisfy requirement {req}"
+    } else if !filter.pre {
+        candidates.retain(|(version, _)| version.pre.is_empty());
+        if candidates.is_empty() {
+            return Err(anyhow!(
+                "no stable versions of {crate_name} have been published; \
+                 use `{crate_name}@next` or --pre to consider prereleases"
+            ));
+        }
+    }
+
+    let matching = requirement
+        .map(|req| format!(" matching {req}"))
+        .unwrap_or_default();
+    let mut notes = Vec::new();
+
+    if let Some(cutoff) = filter.published_before {
+        candidates.retain(|(_, entry)| {
+            entry
+                .created_at
+                .as_deref()
+                .and_then(parse_rfc3339)
+                .is_some_and(|time| time < cutoff)
+        });
+        if candidates.is_empty() {
+            return Err(anyhow!(
+                "no versions of {crate_name}{matching} are known to have been published before {}",
+                format_instant(cutoff)
+            ));
+        }
+    }
+
+    if let Some(min_age) = filter.min_release_age
+        && !requirement.is_some_and(is_exact_pin)
+    {
+        let cutoff = filter.now.saturating_sub_unsigned(min_age);
+        let published = |entry: &CrateVersion| entry.created_at.as_deref().and_then(parse_rfc3339);
+        // Releases without a publish time cannot be judged, so they are let through
+        let settled = |entry: &CrateVersion| published(entry).is_none_or(|time| time <= cutoff);
+        let (newest, newest_entry) = candidates.last().expect("candidates is not empty");
+        let Some(position) = candidates.iter().rposition(|(_, entry)| settled(entry)) else {
+            return Err(anyhow!(
+                "every published version of {crate_name}{matching} is younger than the minimum \
+                 release age of {}; pin one exactly, e.g. `{crate_name}@={newest}`, to use it",
+                format_age(min_age)
+            ));
+        };
+        if position + 1 < candidates.len() {
+            let age = filter.now - published(newest_entry).unwrap_or(filter.now);
+            notes.push(format!(
+                "Skipping {crate_name} {newest}: it was published {} ago, within the minimum release age of {}; using {}",
+                format_age(age.max(0) as u64),
+                format_age(min_age),
+                candidates[position].0
+            ));
+        }
+        candidates.truncate(position + 1);
+    }
+
+    if let Some(rustc) = &filter.rustc {
+        let compatible = |entry: &CrateVersion| {
+            entry
+                .rust_version
+                .as_deref()
+                .and_then(parse_rust_version)
+                .is_none_or(|required| required <= *rustc)
+        };
+        let (newest, newest_entry) = candidates.last().expect("candidates is not empty");
+        let Some(position) = candidates.iter().rposition(|(_, entry)| compatible(entry)) else {
+            return Err(anyhow!(
+                "no published versions of {crate_name}{matching} support rustc {rustc}"
+            ));
+        };
+        if position + 1 < candidates.len() {
+            // The newest candidate was skipped, so it must declare a rust-version
+            let required = newest_entry.rust_version.as_deref().unwrap_or_default();
+            notes.push(format!(
+                "Skipping {crate_name} {newest}: it requires rustc {required}, newer than rustc {rustc}; using {}",
+                candidates[position].0
+            ));
+        }
+        candidates.truncate(position + 1);
+    }
+
+    let (version, entry) = candidates.pop().expect("candidates is not empty");
+    if entry.yanked {
+        notes.push(format!(
+            "warning: {crate_name} {version} has been yanked; using it because it is pinned exactly"
         ));
     }
+    Ok(Selection { version, notes })
+}
 
+/// Whether `version` is among the yanked releases in `versions`.
+pub fn is_yanked(versions: &[CrateVersion], version: &Version) -> bool {
     versions
+        .iter()
+        .any(|entry| entry.yanked && Version::parse(&entry.num).is_ok_and(|num| num == *version))
+}
+
+/// `=1.2.3` names exactly one release, as opposed to `1.2.3`, which means `^1.2.3`.
+pub fn is_exact_pin(requirement: &VersionReq) -> bool {
+    matches!(
+        requirement.comparators.as_slice(),
+        [comparator] if comparator.op == Op::Exact
+            && comparator.minor.is_some()
+            && comparator.patch.is_some()
+    )
+}
+
+/// The `=major.minor.patch[-pre]` requirement naming exactly `version`.
+pub fn exact_requirement(version: &Version) -> VersionReq {
+    VersionReq {
+        comparators: vec![Comparator {
+            op: Op::Exact,
+            major: version.major,
+            minor: Some(version.minor),
+            patch: Some(version.patch),
+            pre: version.pre.clone(),
+        }],
+    }
+}
+
+/// Render an age in seconds with its largest whole unit, e.g. `3 days` or `5 hours`.
+fn format_age(secs: u64) -> String {
+    let (count, unit) = match secs {
+        0..60 => (secs, "second"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / SECS_PER_DAY as u64, "day"),
+    };
+    format!("{count} {unit}{}", if count == 1 { "" } else { "s" })
+}
+
+/// The minimum supported Rust version declared by a published release, if any.
+pub fn fetch_rust_version(crate_name: &str, version: &Version) -> Result<Option<Version>> {
+    let versions = fetch_crate_versions(crate_name)?;
+    Ok(versions
+        .iter()
+        .find(|entry| Version::parse(&entry.num).is_ok_and(|num| num == *version))
+        .and_then(|entry| entry.rust_version.as_deref())
+        .and_then(parse_rust_version))
+}
+
+/// Unpack the `.crate` of a published release into `dir` and return the package
+/// directory. `cargo install` refuses to resolve yanked releases, but builds their
+/// source like any other once it is on disk. The download must match the checksum
+/// the registry lists for the release; without one nothing is unpacked.
+pub fn fetch_crate_source(crate_name: &str, version: &Version, dir: &Path) -> Result<PathBuf> {
+    let expected = fetch_crate_versions(crate_name)?
         .into_iter()
-        .last()
-        .ok_or_else(|| anyhow!("no published versions found for {crate_name}"))
+        .find(|entry| Version::parse(&entry.num).is_ok_and(|num| num == *version))
+        .and_then(|entry| entry.checksum)
+        .ok_or_else(|| {
+            anyhow!(
+                "the registry lists no checksum for {crate_name} {version}, so its .crate cannot be verified"
+            )
+        })?;
+    let bytes = match CargoConfig::load()?.crates_io_source()? {
+        Some(CratesIoSource::LocalRegistry(registry)) => {
+            let path = registry.join(format!("{crate_name}-{version}.crate"));
+            fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?
+        }
+        Some(CratesIoSource::Registry(index)) if index.starts_with("sparse+") => {
+            let template = request_download_template(&index["sparse+".len()..])?;
+            download_crate(&download_url(&template, crate_name, version))?
+        }
+        Some(CratesIoSource::Directory(_) | CratesIoSource::Registry(_)) => {
+            return Err(anyhow!(
+                "cannot fetch {crate_name} {version} from the source that replaces crates.io"
+            ));
+        }
+        None => download_crate(&format!(
+            "https://static.crates.io/crates/{crate_name}/{crate_name}-{version}.crate"
+        ))?,
+    };
+
+    let actual = format!("{:x}", Sha256::digest(&bytes));
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(anyhow!(
+            "checksum mismatch for {crate_name} {version}: the registry lists {expected}, but the download hashes to {actual}"
+        ));
+    }
+
+    tar::Archive::new(GzDecoder::new(bytes.as_slice()))
+        .unpack(dir)
+        .with_context(|| format!("failed to unpack {crate_name} {version}"))?;
+    let package = dir.join(format!("{crate_name}-{version}"));
+    if !package.join("Cargo.toml").is_file() {
+        return Err(anyhow!(
+            "the .crate of {crate_name} {version} has no {crate_name}-{version}/Cargo.toml"
+        ));
+    }
+    Ok(package)
+}
+
+fn download_crate(url: &str) -> Result<Vec<u8>> {
+    let (client, headers) = http_client()?;
+    let bytes = client
+        .get(url)
+        .headers(headers)
+        .timeout(Duration::from_secs(60))
+        .send()
+        .with_context(|| format!("failed to download {url}"))?
+        .error_for_status()
+        .with_context(|| format!("failed to download {url}"))?
+        .bytes()
+        .with_context(|| format!("failed to download {url}"))?;
+    Ok(bytes.to_vec())
+}
+
+#[derive(Deserialize)]
+struct IndexConfig {
+    dl: String,
+}
+
+/// The `dl` template from a sparse registry's `config.json`.
+fn request_download_template(index: &str) -> Result<String> {
+    let (client, headers) = http_client()?;
+    let url = format!("{}/config.json", index.trim_end_matches('/'));
+    let config: IndexConfig = client
+        .get(&url)
+        .headers(headers)
+        .send()
+        .with_context(|| format!("failed to contact registry index {index}"))?
+        .error_for_status()
+        .with_context(|| format!("registry index returned an error status for {url}"))?
+        .json()
+        .with_context(|| format!("failed to parse {url}"))?;
+    Ok(config.dl)
+}
+
+/// Expand a registry `dl` template. Without any markers, cargo appends
+/// `/{crate}/{version}/download`.
+fn download_url(template: &str, crate_name: &str, version: &Version) -> String {
+    const MARKERS: [&str; 4] = ["{crate}", "{version}", "{prefix}", "{lowerprefix}"];
+    if !MARKERS.iter().any(|marker| template.contains(marker)) {
+        return format!(
+            "{}/{crate_name}/{version}/download",
+            template.trim_end_matches('/')
+        );
+    }
+    let prefix = index_path(crate_name)
+        .parent()
+        .expect("index paths have a parent")
+        .to_string_lossy()
+        .replace('\\', "/");
+    template
+        .replace("{crate}", crate_name)
+        .replace("{version}", &version.to_string())
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_ascii_lowercase())
 }
 
-pub fn fetch_latest_version(crate_name: &str) -> Result<Version> {
-    fetch_highest_matching_version(crate_name, None)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::env_lock;
+    use std::env;
+
+    fn version(num: &str, yanked: bool, rust_version: Option<&str>) -> CrateVersion {
+        CrateVersion {
+            num: num.to_string(),
+            yanked,
+            rust_version: rust_version.map(str::to_string),
+            created_at: None,
+            checksum: None,
+        }
+    }
+
+    fn sample() -> Vec<CrateVersion> {
+        vec![
+            version("2.0.0", false, Some("1.85")),
+            version("1.9.3", false, Some("1.70")),
+            version("1.9.2", true, Some("1.70")),
+            version("1.0.0", false, None),
+        ]
+    }
+
+    #[test]
+    fn index_path_follows_registry_layout() {
+        assert_eq!(index_path("a"), Path::new("1").join("a"));
+        assert_eq!(index_path("ab"), Path::new("2").join("ab"));
+        assert_eq!(index_path("syn"), Path::new("3").join("s").join("syn"));
+        assert_eq!(
+            index_path("Serde"),
+            Path::new("se").join("rd").join("serde")
+        );
+    }
+
+    #[test]
+    fn parse_index_entries_reads_index_lines() {
+        let contents = r#"{"name":"tool","vers":"1.0.0","deps":[],"cksum":"00","features":{},"yanked":false}
+{"name":"tool","vers":"1.1.0","deps":[],"cksum":"00","features":{},"yanked":true,"rust_version":"1.70"}
+"#;
+        let versions = parse_index_entries(contents).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[1].num, "1.1.0");
+        assert!(versions[1].yanked);
+        assert_eq!(versions[1].rust_version.as_deref(), Some("1.70"));
+        assert_eq!(versions[1].checksum.as_deref(), Some("00"));
+    }
+
+    #[test]
+    fn download_url_expands_registry_templates() {
+        let version = Version::new(1, 2, 3);
+        assert_eq!(
+            download_url("https://dl.example.com/api/v1/crates/", "serde", &version),
+            "https://dl.example.com/api/v1/crates/serde/1.2.3/download"
+        );
+        assert_eq!(
+            download_url(
+                "https://dl.example.com/{prefix}/{crate}-{version}.crate",
+                "serde",
+                &version
+            ),
+            "https://dl.example.com/se/rd/serde-1.2.3.crate"
+        );
+        assert_eq!(
+            download_url(
+                "https://dl.example.com/{lowerprefix}/{crate}",
+                "syn",
+                &version
+            ),
+            "https://dl.example.com/3/s/syn"
+        );
+    }
+
+    #[test]
+    fn read_directory_versions_lists_vendored_crates() {
+        let temp = tempfile::tempdir().unwrap();
+        for (dir, name, version) in [
+            ("tool", "tool", "1.0.0"),
+            ("tool-0.9.0", "tool", "0.9.0"),
+            ("other", "other", "2.0.0"),
+        ] {
+            let path = temp.path().join(dir);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(
+                path.join("Cargo.toml"),
+                format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\n"),
+            )
+            .unwrap();
+        }
+
+        let mut versions: Vec<String> = read_directory_versions(temp.path(), "tool")
+            .unwrap()
+            .into_iter()
+            .map(|v| v.num)
+            .collect();
+        versions.sort();
+        assert_eq!(versions, ["0.9.0", "1.0.0"]);
+        assert!(read_directory_versions(temp.path(), "missing").is_err());
+    }
+
+    #[test]
+    fn select_version_picks_highest_non_yanked() {
+        let selection = select_version("tool", &sample(), None, &VersionFilter::default()).unwrap();
+        assert_eq!(selection.version, Version::new(2, 0, 0));
+        assert!(selection.notes.is_empty());
+
+        let req = VersionReq::parse("~1.9.2").unwrap();
+        let selection =
+            select_version("tool", &sample(), Some(&req), &VersionFilter::default()).unwrap();
+        assert_eq!(selection.version, Version::new(1, 9, 3));
+    }
+
+    #[test]
+    fn select_version_skips_releases_requiring_newer_rustc() {
+        let filter = VersionFilter {
+            rustc: Some(Version::new(1, 80, 0)),
+            ..VersionFilter::default()
+        };
+        let selection = select_version("tool", &sample(), None, &filter).unwrap();
+        assert_eq!(selection.version, Version::new(1, 9, 3));
+        assert_eq!(selection.notes.len(), 1);
+        assert!(selection.notes[0].contains("requires rustc 1.85"));
+    }
+
+    #[test]
+    fn select_version_skips_releases_younger_than_min_release_age() {
+        let mut versions = sample();
+        versions[0].created_at = Some("2024-03-09T12:00:00Z".to_string());
+        versions[1].created_at = Some("2024-03-01T00:00:00Z".to_string());
+        let filter = VersionFilter {
+            min_release_age: Some(3 * 86400),
+            now: parse_rfc3339("2024-03-10T00:00:00Z").unwrap(),
+            ..VersionFilter::default()
+        };
+
+        let selection = select_version("tool", &versions, None, &filter).unwrap();
+        assert_eq!(selection.version, Version::new(1, 9, 3));
+        assert_eq!(
+            selection.notes,
+            vec![
+                "Skipping tool 2.0.0: it was published 12 hours ago, within the minimum release age of 3 days; using 1.9.3"
+            ]
+        );
+
+        let pinned = VersionReq::parse("=2.0.0").unwrap();
+        let selection = select_version("tool", &versions, Some(&pinned), &filter).unwrap();
+        assert_eq!(selection.version, Version::new(2, 0, 0));
+        assert!(selection.notes.is_empty());
+
+        let caret = VersionReq::parse("2.0.0").unwrap();
+        let err = select_version("tool", &versions, Some(&caret), &filter).unwrap_err();
+        assert!(err.to_string().contains("`tool@=2.0.0`"), "{err}");
+    }
+
+    #[test]
+    fn select_version_only_picks_prereleases_when_asked() {
+        let mut versions = sample();
+        versions.insert(0, version("2.1.0-beta.3", false, None));
+        let pre = VersionFilter {
+            pre: true,
+            ..VersionFilter::default()
+        };
+
+        let stable = select_version("tool", &versions, None, &VersionFilter::default()).unwrap();
+        assert_eq!(stable.version, Version::parse("2.0.0").unwrap());
+        let next = select_version("tool", &versions, None, &pre).unwrap();
+        assert_eq!(next.version, Version::parse("2.1.0-beta.3").unwrap());
+
+        let req = VersionReq::parse("^2").unwrap();
+        let stable =
+            select_version("tool", &versions, Some(&req), &VersionFilter::default()).unwrap();
+        assert_eq!(stable.version, Version::parse("2.0.0").unwrap());
+        let next = select_version("tool", &versions, Some(&req), &pre).unwrap();
+        assert_eq!(next.version, Version::parse("2.1.0-beta.3").unwrap());
+
+        let only_beta = [version("0.1.0-alpha.1", false, None)];
+        let err = select_version("tool", &only_beta, None, &VersionFilter::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`tool@next`"), "{err}");
+    }
+
+    #[test]
+    fn select_version_only_picks_releases_published_before_the_cutoff() {
+        let mut versions = sample();
+        versions[0].created_at = Some("2024-03-05T10:00:00Z".to_string());
+        versions[1].created_at = Some("2024-02-20T10:00:00.123+00:00".to_string());
+        versions[3].created_at = Some("2023-06-01T10:00:00Z".to_string());
+        let filter = |date| VersionFilter {
+            published_before: timestamp::parse_instant(date),
+            ..VersionFilter::default()
+        };
+
+        let selection = select_version("tool", &versions, None, &filter("2024-03-01")).unwrap();
+        assert_eq!(selection.version, Version::parse("1.9.3").unwrap());
+        let selection = select_version("tool", &versions, None, &filter("2024-03-06")).unwrap();
+        assert_eq!(selection.version, Version::parse("2.0.0").unwrap());
+
+        let req = VersionReq::parse("^1.9").unwrap();
+        let err = select_version("tool", &versions, Some(&req), &filter("2024-01-01"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "no versions of tool matching ^1.9 are known to have been published before 2024-01-01"
+        );
+    }
+
+    #[test]
+    fn select_version_allows_yanked_exact_pins() {
+        let pinned = VersionReq::parse("=1.9.2").unwrap();
+        let selection =
+            select_version("tool", &sample(), Some(&pinned), &VersionFilter::default()).unwrap();
+        assert_eq!(selection.version, Version::new(1, 9, 2));
+        assert_eq!(
+            selection.notes,
+            vec!["warning: tool 1.9.2 has been yanked; using it because it is pinned exactly"]
+        );
+
+        let tilde = VersionReq::parse("~1.9.2").unwrap();
+        let selection =
+            select_version("tool", &sample(), Some(&tilde), &VersionFilter::default()).unwrap();
+        assert_eq!(selection.version, Version::new(1, 9, 3));
+        assert!(selection.notes.is_empty());
+
+        let range = VersionReq::parse(">=1.9.2, <1.9.3").unwrap();
+        assert!(
+            select_version("tool", &sample(), Some(&range), &VersionFilter::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn is_exact_pin_requires_a_full_version() {
+        assert!(is_exact_pin(&VersionReq::parse("=1.2.3").unwrap()));
+        assert!(is_exact_pin(&VersionReq::parse("=1.2.3-beta.1").unwrap()));
+        assert!(!is_exact_pin(&VersionReq::parse("1.2.3").unwrap()));
+        assert!(!is_exact_pin(&VersionReq::parse("=1.2").unwrap()));
+        assert!(!is_exact_pin(&VersionReq::parse(">=1.2.3, <2").unwrap()));
+
+        let beta = Version::parse("0.2.0-beta.1").unwrap();
+        let pin = exact_requirement(&beta);
+        assert_eq!(pin.to_string(), "=0.2.0-beta.1");
+        assert!(is_exact_pin(&pin) && pin.matches(&beta));
+    }
+
+    #[test]
+    fn known_crate_versions_only_trusts_a_fresh_cache() {
+        let _guard = env_lock().lock().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        unsafe {
+            env::set_var("CARGOX_INSTALL_DIR", temp.path());
+        }
+
+        let missing = known_crate_versions("cached-tool").unwrap();
+        save_cached_versions("cached-tool", &sample()).unwrap();
+        let fresh = known_crate_versions("cached-tool").unwrap();
+
+        let path = versions_cache_path("cached-tool").unwrap();
+        let stale = CachedVersions {
+            fetched_at: timestamp::now() - 2 * SECS_PER_DAY,
+            versions: sample(),
+        };
+        fs::write(&path, serde_json::to_vec(&stale).unwrap()).unwrap();
+        let expired = known_crate_versions("cached-tool").unwrap();
+        fs::write(&path, "not json").unwrap();
+        let corrupt = known_crate_versions("cached-tool").unwrap();
+
+        unsafe {
+            env::remove_var("CARGOX_INSTALL_DIR");
+        }
+
+        assert!(missing.is_none());
+        let fresh = fresh.unwrap();
+        assert_eq!(fresh.len(), 4);
+        assert!(is_yanked(&fresh, &Version::new(1, 9, 2)));
+        assert!(!is_yanked(&fresh, &Version::new(1, 9, 3)));
+        assert!(expired.is_none());
+        assert!(corrupt.is_none());
+    }
+
+    #[test]
+    fn select_version_treats_missing_rust_version_as_compatible() {
+        let filter = VersionFilter {
+            rustc: Some(Version::new(1, 60, 0)),
+            ..VersionFilter::default()
+        };
+        let selection = select_version("tool", &sample(), None, &filter).unwrap();
+        assert_eq!(selection.version, Version::new(1, 0, 0));
+
+        let req = VersionReq::parse(">=1.9").unwrap();
+        let err = select_version("tool", &sample(), Some(&req), &filter).unwrap_err();
+        assert!(err.to_string().contains("support rustc 1.60.0"));
+    }
 }
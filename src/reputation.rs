@@ -0,0 +1,310 @@
+use crate::registry::CrateInfo;
+use crate::timestamp::{SECS_PER_DAY, parse_rfc3339};
+
+/// Crates first published more recently than this are flagged as new.
+const NEW_CRATE_DAYS: i64 = 30;
+/// Crates downloaded fewer times than this are flagged as little used.
+const FEW_DOWNLOADS: u64 = 1_000;
+
+/// Widely used crates, including the libraries most often imitated by typosquats.
+const POPULAR_CRATES: &[&str] = &[
+    // Command line tools
+    "ast-grep",
+    "bacon",
+    "bat",
+    "bottom",
+    "broot",
+    "cargo-audit",
+    "cargo-binstall",
+    "cargo-bloat",
+    "cargo-chef",
+    "cargo-deny",
+    "cargo-dist",
+    "cargo-edit",
+    "cargo-expand",
+    "cargo-generate",
+    "cargo-hack",
+    "cargo-insta",
+    "cargo-llvm-cov",
+    "cargo-machete",
+    "cargo-make",
+    "cargo-msrv",
+    "cargo-nextest",
+    "cargo-outdated",
+    "cargo-release",
+    "cargo-semver-checks",
+    "cargo-tarpaulin",
+    "cargo-udeps",
+    "cargo-update",
+    "cargo-watch",
+    "cargo-zigbuild",
+    "cross",
+    "diesel_cli",
+    "difftastic",
+    "du-dust",
+    "eza",
+    "exa",
+    "fd-find",
+    "flamegraph",
+    "git-cliff",
+    "git-delta",
+    "hyperfine",
+    "just",
+    "lsd",
+    "mdbook",
+    "miniserve",
+    "nu",
+    "procs",
+    "ripgrep",
+    "sccache",
+    "sd",
+    "sqlx-cli",
+    "starship",
+    "taplo-cli",
+    "tealdeer",
+    "tokei",
+    "trunk",
+    "typos-cli",
+    "wasm-bindgen-cli",
+    "wasm-pack",
+    "watchexec-cli",
+    "xh",
+    "zellij",
+    "zoxide",
+    // Libraries
+    "anyhow",
+    "async-trait",
+    "axum",
+    "base64",
+    "bitflags",
+    "byteorder",
+    "bytes",
+    "cc",
+    "cfg-if",
+    "chrono",
+    "clap",
+    "crossbeam",
+    "diesel",
+    "env_logger",
+    "futures",
+    "getrandom",
+    "hashbrown",
+    "http",
+    "hyper",
+    "indexmap",
+    "itertools",
+    "lazy_static",
+    "libc",
+    "log",
+    "memchr",
+    "nom",
+    "num-traits",
+    "once_cell",
+    "openssl",
+    "parking_lot",
+    "proc-macro2",
+    "quote",
+    "rand",
+    "rayon",
+    "regex",
+    "reqwest",
+    "ring",
+    "rustls",
+    "semver",
+    "serde",
+    "serde_derive",
+    "serde_json",
+    "sha2",
+    "smallvec",
+    "sqlx",
+    "syn",
+    "tempfile",
+    "thiserror",
+    "time",
+    "tokio",
+    "toml",
+    "tower",
+    "tracing",
+    "tracing-subscriber",
+    "url",
+    "uuid",
+    "walkdir",
+    "wasm-bindgen",
+];
+
+/// Binaries whose name differs from the popular crate that ships them, so that
+/// `cargox rg` is not mistaken for `ripgrep`.
+const POPULAR_BINARIES: &[(&str, &str)] = &[
+    ("btm", "bottom"),
+    ("delta", "git-delta"),
+    ("difft", "difftastic"),
+    ("dust", "du-dust"),
+    ("fd", "fd-find"),
+    ("rg", "ripgrep"),
+    ("sg", "ast-grep"),
+    ("taplo", "taplo-cli"),
+    ("tldr", "tealdeer"),
+    ("typos", "typos-cli"),
+    ("watchexec", "watchexec-cli"),
+];
+
+/// Reasons to double check a crate before trusting it, one sentence each.
+pub fn warnings(crate_name: &str, info: Option<&CrateInfo>, now: i64) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(binary_of) = binary_of_popular_crate(crate_name) {
+        warnings.push(format!(
+            "`{crate_name}` is the binary of the popular crate `{binary_of}`; the crate named \
+             `{crate_name}` is unrelated (run `cargox --bin {crate_name} {binary_of}` instead)"
+        ));
+    } else if let Some(popular) = lookalike(crate_name) {
+        warnings.push(format!(
+            "`{crate_name}` looks like the popular crate `{popular}`; check the name for a typo"
+        ));
+    }
+
+    let Some(info) = info else {
+        return warnings;
+    };
+    if let Some(created) = info.created_at.as_deref().and_then(parse_rfc3339) {
+        let age = (now - created).div_euclid(SECS_PER_DAY);
+        if age < NEW_CRATE_DAYS {
+            warnings.push(match age {
+                ..=0 => format!("`{crate_name}` was first published today"),
+                1 => format!("`{crate_name}` was first published yesterday"),
+                days => format!("`{crate_name}` was first published {days} days ago"),
+            });
+        }
+    }
+    if info.downloads < FEW_DOWNLOADS {
+        warnings.push(format!(
+            "`{crate_name}` has only been downloaded {} times",
+            info.downloads
+        ));
+    }
+    warnings
+}
+
+fn binary_of_popular_crate(crate_name: &str) -> Option<&'static str> {
+    let name = normalize(crate_name);
+    POPULAR_BINARIES
+        .iter()
+        .find(|(binary, _)| *binary == name)
+        .map(|(_, crate_name)| *crate_name)
+}
+
+/// The popular crate that `crate_name` imitates: the same name with separators
+/// added, removed or swapped, or one edit away from a name of five or more letters.
+fn lookalike(crate_name: &str) -> Option<&'static str> {
+    let name = normalize(crate_name);
+    if POPULAR_CRATES
+        .iter()
+        .any(|popular| normalize(popular) == name)
+    {
+        return None;
+    }
+
+    let compact = |name: &str| name.replace(['-', '_'], "");
+    POPULAR_CRATES.iter().copied().find(|popular| {
+        let popular_name = normalize(popular);
+        compact(&popular_name) == compact(&name)
+            || (popular_name.len() >= 5 && edit_distance(&popular_name, &name) <= 1)
+    })
+}
+
+/// crates.io treats names as equal regardless of case and of `-` versus `_`.
+fn normalize(name: &str) -> String {
+    name.trim().to_ascii_lowercase().replace('_', "-")
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and
+/// swaps of adjacent characters each cost one.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(downloads: u64, created_at: &str) -> CrateInfo {
+        CrateInfo {
+            downloads,
+            created_at: Some(created_at.to_string()),
+            ..CrateInfo::default()
+        }
+    }
+
+    #[test]
+    fn lookalike_catches_typos_and_separator_games() {
+        assert_eq!(lookalike("ripgerp"), Some("ripgrep"));
+        assert_eq!(lookalike("rip-grep"), Some("ripgrep"));
+        assert_eq!(lookalike("fdfind"), Some("fd-find"));
+        assert_eq!(lookalike("cargo-nextset"), Some("cargo-nextest"));
+        assert_eq!(lookalike("serde-jsonn"), Some("serde_json"));
+        assert_eq!(lookalike("ripgrep"), None);
+        assert_eq!(lookalike("Serde-Json"), None);
+        // Short names are too close to everything to compare by edit distance
+        assert_eq!(lookalike("cat"), None);
+        assert_eq!(lookalike("my-tool"), None);
+    }
+
+    #[test]
+    fn edit_distance_counts_adjacent_swaps_once() {
+        assert_eq!(edit_distance("tokio", "tokio"), 0);
+        assert_eq!(edit_distance("tokio", "toiko"), 1);
+        assert_eq!(edit_distance("tokio", "tokyo"), 1);
+        assert_eq!(edit_distance("tokio", "tokios"), 1);
+        assert_eq!(edit_distance("tokio", "kotio"), 2);
+    }
+
+    #[test]
+    fn warnings_flag_binaries_new_crates_and_few_downloads() {
+        let now = parse_rfc3339("2024-03-10T12:00:00Z").unwrap();
+        let rg = warnings("rg", None, now);
+        assert_eq!(rg.len(), 1);
+        assert!(
+            rg[0].contains("binary of the popular crate `ripgrep`"),
+            "{rg:?}"
+        );
+
+        let established = info(50_000, "2021-06-01T08:00:00Z");
+        assert!(warnings("my-tool", Some(&established), now).is_empty());
+
+        let fresh = warnings("my-tool", Some(&info(12, "2024-03-07T09:00:00Z")), now);
+        assert_eq!(
+            fresh,
+            vec![
+                "`my-tool` was first published 3 days ago",
+                "`my-tool` has only been downloaded 12 times",
+            ]
+        );
+        let today = warnings(
+            "my-tool",
+            Some(&info(5_000, "2024-03-10T10:00:00+01:00")),
+            now,
+        );
+        assert_eq!(today, vec!["`my-tool` was first published today"]);
+    }
+}
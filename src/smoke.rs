@@ -0,0 +1,227 @@
+use anyhow::{Context, Result, anyhow};
+use semver::Version;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::SmokeTestConfig;
+use crate::executor::Invocation;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Run the probe for `crate_name` against a freshly installed binary, started as
+/// `binary_name` would be when run. The default probe is `--version`, whose output
+/// must mention `expected` when it is known.
+pub fn smoke_test(
+    config: &SmokeTestConfig,
+    crate_name: &str,
+    binary_name: &str,
+    binary: &Path,
+    expected: Option<&Version>,
+) -> Result<()> {
+    let probe = config.probes.get(crate_name);
+    let probe_args =
+        probe.map_or_else(|| vec!["--version".to_string()], |probe| probe.args.clone());
+    let probe_args: Vec<OsString> = probe_args.into_iter().map(OsString::from).collect();
+    // A cargo subcommand gets its name first and `CARGO`, as it would when run
+    let invocation = Invocation::new(binary_name, &probe_args);
+    let check_version = probe.is_none_or(|probe| probe.check_version);
+    let timeout = Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let args: Vec<String> = invocation
+        .args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let command = format!("{} {}", binary.display(), args.join(" "));
+
+    let (status, output) = run_with_timeout(binary, &invocation, timeout)
+        .with_context(|| format!("`{command}` could not be run"))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "`{command}` exited with {status}{}",
+            last_line(&output)
+                .map(|line| format!(": {line}"))
+                .unwrap_or_default()
+        ));
+    }
+
+    if check_version
+        && let Some(expected) = expected
+        && !mentions_version(&output, expected)
+    {
+        return Err(anyhow!(
+            "`{command}` reported {:?}, expected version {expected}",
+            output.lines().next().unwrap_or_default().trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Run `binary` as `invocation` with its output captured in a scratch directory,
+/// killing it once `timeout` has passed. Returns the exit status and the combined
+/// stdout and stderr.
+fn run_with_timeout(
+    binary: &Path,
+    invocation: &Invocation,
+    timeout: Duration,
+) -> Result<(ExitStatus, String)> {
+    let scratch = tempfile::tempdir().context("failed to create temp directory")?;
+    let stdout_path = scratch.path().join("stdout");
+    let stderr_path = scratch.path().join("stderr");
+
+    let mut child = Command::new(binary)
+        .args(&invocation.args)
+        .envs(invocation.env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(fs::File::create(&stdout_path)?)
+        .stderr(fs::File::create(&stderr_path)?)
+        .spawn()?;
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("timed out after {}s", timeout.as_secs()));
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    let mut output = String::from_utf8_lossy(&fs::read(&stdout_path)?).into_owned();
+    output.push_str(&String::from_utf8_lossy(&fs::read(&stderr_path)?));
+    Ok((status, output))
+}
+
+/// Whether `output` names `version` as a whole token: `v1.2.3` and `1.2.3,` do, but
+/// `11.2.3`, `1.2.30` and `1.2.3-rc.1` do not.
+fn mentions_version(output: &str, version: &Version) -> bool {
+    let version = version.to_string();
+    output.match_indices(&version).any(|(start, _)| {
+        let before = output[..start].chars().next_back();
+        let mut after = output[start + version.len()..].chars();
+        let continues = match after.next() {
+            Some(c) if c.is_ascii_digit() => true,
+            Some('.' | '-') => after.next().is_some_and(|c| c.is_ascii_alphanumeric()),
+            _ => false,
+        };
+        !before.is_some_and(|c| c.is_ascii_digit() || c == '.') && !continues
+    })
+}
+
+fn last_line(output: &str) -> Option<&str> {
+    output.lines().map(str::trim).rfind(|line| !line.is_empty())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::config::SmokeProbe;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(dir: &Path, body: &str) -> std::path::PathBuf {
+        let path = dir.join("tool");
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn smoke_test_checks_reported_version() {
+        let temp = tempfile::tempdir().unwrap();
+        let tool = script(temp.path(), "echo \"tool $1 1.2.3\"");
+        let config = SmokeTestConfig::default();
+
+        smoke_test(&config, "tool", "tool", &tool, Some(&Version::new(1, 2, 3))).unwrap();
+        smoke_test(&config, "tool", "tool", &tool, None).unwrap();
+        let err =
+            smoke_test(&config, "tool", "tool", &tool, Some(&Version::new(1, 2, 4))).unwrap_err();
+        assert!(err.to_string().contains("expected version 1.2.4"), "{err}");
+    }
+
+    #[test]
+    fn smoke_test_starts_cargo_subcommands_like_cargo() {
+        let temp = tempfile::tempdir().unwrap();
+        let tool = script(
+            temp.path(),
+            "[ \"$1\" = tool ] && [ -n \"$CARGO\" ] && echo \"cargo-tool 1.2.3\" && exit 0\nexit 3",
+        );
+        let config = SmokeTestConfig::default();
+
+        smoke_test(
+            &config,
+            "cargo-tool",
+            "cargo-tool",
+            &tool,
+            Some(&Version::new(1, 2, 3)),
+        )
+        .unwrap();
+        let err = smoke_test(&config, "tool", "tool", &tool, None).unwrap_err();
+        assert!(err.to_string().contains("exited with"), "{err}");
+    }
+
+    #[test]
+    fn mentions_version_matches_whole_versions_only() {
+        let version = Version::new(1, 2, 3);
+        for output in [
+            "tool 1.2.3",
+            "tool v1.2.3 (abc123)",
+            "tool-1.2.3\n",
+            "version: 1.2.3.",
+            "1.2.3+build",
+        ] {
+            assert!(mentions_version(output, &version), "{output}");
+        }
+        for output in [
+            "tool 1.2.30",
+            "tool 11.2.3",
+            "tool 0.1.2.3",
+            "tool 1.2.3.4",
+            "tool 1.2.3-rc.1",
+        ] {
+            assert!(!mentions_version(output, &version), "{output}");
+        }
+        assert!(mentions_version("tool 11.2.3 (1.2.3)", &version));
+    }
+
+    #[test]
+    fn smoke_test_uses_configured_probe_and_reports_failures() {
+        let temp = tempfile::tempdir().unwrap();
+        let tool = script(
+            temp.path(),
+            "[ \"$1\" = version ] && echo ok && exit 0\necho \"unknown flag $1\" >&2\nexit 2",
+        );
+        let mut config = SmokeTestConfig::default();
+
+        let err = smoke_test(&config, "tool", "tool", &tool, None).unwrap_err();
+        assert!(err.to_string().contains("unknown flag --version"), "{err}");
+
+        config.probes.insert(
+            "tool".to_string(),
+            SmokeProbe {
+                args: vec!["version".to_string()],
+                check_version: false,
+            },
+        );
+        smoke_test(&config, "tool", "tool", &tool, Some(&Version::new(9, 9, 9))).unwrap();
+    }
+
+    #[test]
+    fn smoke_test_times_out() {
+        let temp = tempfile::tempdir().unwrap();
+        let tool = script(temp.path(), "sleep 5");
+        let config = SmokeTestConfig {
+            timeout_secs: Some(0),
+            ..SmokeTestConfig::default()
+        };
+
+        let err = smoke_test(&config, "tool", "tool", &tool, None).unwrap_err();
+        assert!(format!("{err:#}").contains("timed out"), "{err:#}");
+    }
+}
@@ -1,21 +1,142 @@
 use anyhow::{Result, anyhow};
 use semver::VersionReq;
+use std::path::{Path, PathBuf};
+
+use crate::local::LocalPackage;
+use crate::timestamp::{SECS_PER_DAY, parse_date};
 
 #[derive(Debug)]
 pub struct Target {
     pub crate_name: String,
     pub version: VersionSpec,
     pub binary: String,
+    /// Whether the binary was chosen with `--bin` or `crate:bin`, so that installers
+    /// build only that one
+    pub explicit_binary: bool,
+    pub source: Source,
+    /// Target triple to install for when it differs from the host
+    pub platform: Option<String>,
+    /// Only consider registry releases published before this Unix time
+    pub published_before: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Registry,
+    Git(GitSource),
+    Path(LocalPackage),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSource {
+    pub path: PathBuf,
+    /// Workspace member to install when `path` is a workspace root
+    pub package: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSource {
+    pub url: String,
+    /// Branch, tag or revision following `#` in the spec
+    pub reference: Option<String>,
+    /// Crate to install when the repository is a workspace
+    pub package: Option<String>,
+    /// Binary named with `crate:bin@git+<url>`
+    pub binary: Option<String>,
+}
+
+impl GitSource {
+    /// Name used for the binary when neither a package nor `--bin` is given:
+    /// the last path segment of the repository URL without any `.git` suffix.
+    pub fn default_name(&self) -> &str {
+        let trimmed = self.url.trim_end_matches('/');
+        let last = trimmed.rsplit(['/', ':']).next().unwrap_or(trimmed);
+        last.strip_suffix(".git").unwrap_or(last)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum VersionSpec {
     Unspecified,
     Latest,
+    /// The newest release, prereleases included
+    Next,
+    /// The version of this library locked by the project's `Cargo.lock`
+    Lock(String),
     Requirement(VersionReq),
 }
 
-pub fn parse_spec(spec: &str) -> Result<(String, VersionSpec)> {
+/// Parse a `[crate[:bin]@]git+<url>[#branch|tag|rev]` spec. Returns `None` when the spec
+/// does not refer to a git repository.
+pub fn parse_git_spec(spec: &str) -> Result<Option<GitSource>> {
+    let spec = spec.trim();
+    let (package, binary, rest) = if let Some(rest) = spec.strip_prefix("git+") {
+        (None, None, rest)
+    } else if let Some((name, rest)) = spec.split_once("@git+") {
+        let (name, binary) = split_binary(spec, name)?;
+        if name.is_empty() {
+            return Err(anyhow!("crate name cannot be empty"));
+        }
+        (Some(name.to_owned()), binary, rest)
+    } else {
+        return Ok(None);
+    };
+
+    let (url, reference) = match rest.split_once('#') {
+        Some((url, reference)) => {
+            let reference = reference.trim();
+            if reference.is_empty() {
+                return Err(anyhow!(
+                    "invalid git spec `{spec}`: reference cannot be empty after `#`"
+                ));
+            }
+            (url, Some(reference.to_owned()))
+        }
+        None => (rest, None),
+    };
+
+    if url.trim().is_empty() {
+        return Err(anyhow!(
+            "invalid git spec `{spec}`: repository URL cannot be empty"
+        ));
+    }
+
+    Ok(Some(GitSource {
+        url: url.trim().to_owned(),
+        reference,
+        package,
+        binary,
+    }))
+}
+
+/// Specs that look like filesystem paths (`.`, `./tool`, `../tool`, `/abs/tool`) refer to
+/// a local crate rather than a registry crate name.
+pub fn parse_path_spec(spec: &str) -> Option<PathBuf> {
+    let spec = spec.trim();
+    let is_path = spec == "."
+        || spec == ".."
+        || ["./", "../", ".\\", "..\\"]
+            .iter()
+            .any(|prefix| spec.starts_with(prefix))
+        || spec.starts_with('/')
+        || Path::new(spec).is_absolute();
+
+    is_path.then(|| PathBuf::from(spec))
+}
+
+/// A registry crate spec, `crate[:bin][@version]`.
+#[derive(Debug)]
+pub struct CrateSpec {
+    pub name: String,
+    /// Binary to run when it differs from the crate name, as `--bin` would select
+    pub binary: Option<String>,
+    pub version: VersionSpec,
+    /// Only consider releases published before this Unix time, from a `<YYYY-MM-DD`
+    /// bound in the version
+    pub published_before: Option<i64>,
+}
+
+pub fn parse_spec(spec: &str) -> Result<CrateSpec> {
     if spec.trim().is_empty() {
         return Err(anyhow!("crate spec cannot be empty"));
     }
@@ -26,22 +147,46 @@ pub fn parse_spec(spec: &str) -> Result<(String, VersionSpec)> {
         .expect("split always yields at least one element")
         .trim();
 
-    if first.is_empty() {
+    let (name, binary) = split_binary(spec, first)?;
+
+    if name.is_empty() {
         return Err(anyhow!("crate name cannot be empty"));
     }
 
     let rest: Vec<&str> = parts.collect();
-    if rest.is_empty() {
-        return Ok((first.to_owned(), VersionSpec::Unspecified));
-    }
-
     if rest.len() > 1 {
         return Err(anyhow!(
             "invalid crate spec `{spec}`: expected at most one `@version` suffix"
         ));
     }
 
-    let version = rest[0].trim();
+    let (version, published_before) = match rest.first() {
+        Some(version) => parse_version(spec, version.trim())?,
+        None => (VersionSpec::Unspecified, None),
+    };
+    Ok(CrateSpec {
+        name: name.to_owned(),
+        binary,
+        version,
+        published_before,
+    })
+}
+
+/// Split `crate:bin` into the crate name and the binary to run.
+fn split_binary<'a>(spec: &str, name: &'a str) -> Result<(&'a str, Option<String>)> {
+    let Some((name, binary)) = name.split_once(':') else {
+        return Ok((name.trim(), None));
+    };
+    let binary = binary.trim();
+    if binary.is_empty() || binary.contains(':') {
+        return Err(anyhow!(
+            "invalid crate spec `{spec}`: expected a single binary name after `:`"
+        ));
+    }
+    Ok((name.trim(), Some(binary.to_owned())))
+}
+
+fn parse_version(spec: &str, version: &str) -> Result<(VersionSpec, Option<i64>)> {
     if version.is_empty() {
         return Err(anyhow!(
             "invalid crate spec `{spec}`: version cannot be empty after `@`"
@@ -49,16 +194,52 @@ pub fn parse_spec(spec: &str) -> Result<(String, VersionSpec)> {
     }
 
     if version.eq_ignore_ascii_case("latest") {
-        return Ok((first.to_owned(), VersionSpec::Latest));
+        return Ok((VersionSpec::Latest, None));
+    }
+
+    if version.eq_ignore_ascii_case("next") {
+        return Ok((VersionSpec::Next, None));
+    }
+
+    if let Some(library) = version.strip_prefix("lock:") {
+        let library = library.trim();
+        if library.is_empty() {
+            return Err(anyhow!(
+                "invalid crate spec `{spec}`: expected a library name after `lock:`"
+            ));
+        }
+        return Ok((VersionSpec::Lock(library.to_owned()), None));
     }
 
-    let requirement = VersionReq::parse(version).map_err(|err| {
+    // A `<YYYY-MM-DD` bound may stand alone or join a requirement, as in `@^0.14, <2024-03-01`
+    let mut published_before = None;
+    let mut comparators = Vec::new();
+    for comparator in version.split(',').map(str::trim) {
+        let date = comparator
+            .strip_prefix('<')
+            .and_then(|date| parse_date(date.trim()));
+        match date {
+            Some(_) if published_before.is_some() => {
+                return Err(anyhow!(
+                    "invalid crate spec `{spec}`: expected at most one date bound"
+                ));
+            }
+            Some(days) => published_before = Some(days * SECS_PER_DAY),
+            None => comparators.push(comparator),
+        }
+    }
+    if comparators.is_empty() {
+        return Ok((VersionSpec::Unspecified, published_before));
+    }
+
+    let version = comparators.join(", ");
+    let requirement = VersionReq::parse(&version).map_err(|err| {
         anyhow!(
             "invalid crate spec `{spec}`: failed to parse version requirement `{version}`: {err}"
         )
     })?;
 
-    Ok((first.to_owned(), VersionSpec::Requirement(requirement)))
+    Ok((VersionSpec::Requirement(requirement), published_before))
 }
 
 #[cfg(test)]
@@ -67,14 +248,14 @@ mod tests {
 
     #[test]
     fn split_spec_without_version() {
-        let (name, version) = parse_spec("ripgrep").unwrap();
+        let CrateSpec { name, version, .. } = parse_spec("ripgrep").unwrap();
         assert_eq!(name, "ripgrep");
         assert!(matches!(version, VersionSpec::Unspecified));
     }
 
     #[test]
     fn split_spec_with_version_requirement() {
-        let (name, version) = parse_spec("ripgrep@13.0.0").unwrap();
+        let CrateSpec { name, version, .. } = parse_spec("ripgrep@13.0.0").unwrap();
         assert_eq!(name, "ripgrep");
         match version {
             VersionSpec::Requirement(req) => {
@@ -84,6 +265,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn split_spec_with_binary() {
+        let spec = parse_spec("sqlx-cli:sqlx@0.7").unwrap();
+        assert_eq!(spec.name, "sqlx-cli");
+        assert_eq!(spec.binary.as_deref(), Some("sqlx"));
+        assert!(matches!(spec.version, VersionSpec::Requirement(_)));
+
+        let spec =
+            parse_spec("wasm-bindgen-cli:wasm-bindgen-test-runner@lock:wasm-bindgen").unwrap();
+        assert_eq!(spec.binary.as_deref(), Some("wasm-bindgen-test-runner"));
+        assert!(matches!(spec.version, VersionSpec::Lock(_)));
+
+        assert_eq!(parse_spec("ripgrep").unwrap().binary, None);
+        assert!(parse_spec("sqlx-cli:").is_err());
+        assert!(parse_spec(":sqlx").is_err());
+        assert!(parse_spec("sqlx-cli:sqlx:extra").is_err());
+    }
+
     #[test]
     fn split_spec_rejects_empty() {
         assert!(parse_spec("").is_err());
@@ -94,8 +293,113 @@ mod tests {
 
     #[test]
     fn split_spec_parses_latest() {
-        let (name, version) = parse_spec("ripgrep@latest").unwrap();
+        let CrateSpec { name, version, .. } = parse_spec("ripgrep@latest").unwrap();
         assert_eq!(name, "ripgrep");
         assert!(matches!(version, VersionSpec::Latest));
     }
+
+    #[test]
+    fn split_spec_parses_next() {
+        let CrateSpec { name, version, .. } = parse_spec("cargo-dist@Next").unwrap();
+        assert_eq!(name, "cargo-dist");
+        assert!(matches!(version, VersionSpec::Next));
+    }
+
+    #[test]
+    fn split_spec_parses_lock() {
+        let CrateSpec { name, version, .. } =
+            parse_spec("wasm-bindgen-cli@lock:wasm-bindgen").unwrap();
+        assert_eq!(name, "wasm-bindgen-cli");
+        match version {
+            VersionSpec::Lock(library) => assert_eq!(library, "wasm-bindgen"),
+            other => panic!("unexpected version spec: {other:?}"),
+        }
+        assert!(parse_spec("wasm-bindgen-cli@lock:").is_err());
+    }
+
+    #[test]
+    fn split_spec_parses_date_bounds() {
+        let spec = parse_spec("cargo-deny@<2024-03-01").unwrap();
+        assert!(matches!(spec.version, VersionSpec::Unspecified));
+        assert_eq!(
+            spec.published_before,
+            parse_date("2024-03-01").map(|d| d * SECS_PER_DAY)
+        );
+
+        let spec = parse_spec("cargo-deny@0.14, <2024-03-01").unwrap();
+        match spec.version {
+            VersionSpec::Requirement(req) => assert_eq!(req.to_string(), "^0.14"),
+            other => panic!("unexpected version spec: {other:?}"),
+        }
+        assert!(spec.published_before.is_some());
+
+        // Without a full date the bound is an ordinary version comparator
+        let spec = parse_spec("cargo-deny@<2024").unwrap();
+        assert!(matches!(spec.version, VersionSpec::Requirement(_)));
+        assert_eq!(spec.published_before, None);
+
+        assert!(parse_spec("cargo-deny@<2024-03-01,<2024-04-01").is_err());
+        assert!(parse_spec("cargo-deny@<2024-02-30").is_err());
+    }
+
+    #[test]
+    fn parse_path_spec_recognizes_paths() {
+        assert_eq!(parse_path_spec("."), Some(PathBuf::from(".")));
+        assert_eq!(
+            parse_path_spec("./tools/codegen"),
+            Some(PathBuf::from("./tools/codegen"))
+        );
+        assert_eq!(
+            parse_path_spec("../codegen"),
+            Some(PathBuf::from("../codegen"))
+        );
+        assert_eq!(
+            parse_path_spec("/opt/codegen"),
+            Some(PathBuf::from("/opt/codegen"))
+        );
+        assert_eq!(parse_path_spec("codegen"), None);
+        assert_eq!(parse_path_spec("codegen@1.0"), None);
+    }
+
+    #[test]
+    fn parse_git_spec_ignores_registry_specs() {
+        assert!(parse_git_spec("ripgrep").unwrap().is_none());
+        assert!(parse_git_spec("ripgrep@14").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_git_spec_with_reference() {
+        let git = parse_git_spec("git+https://github.com/BurntSushi/ripgrep.git#14.1.0")
+            .unwrap()
+            .unwrap();
+        assert_eq!(git.url, "https://github.com/BurntSushi/ripgrep.git");
+        assert_eq!(git.reference.as_deref(), Some("14.1.0"));
+        assert_eq!(git.package, None);
+        assert_eq!(git.default_name(), "ripgrep");
+    }
+
+    #[test]
+    fn parse_git_spec_with_package() {
+        let git = parse_git_spec("wasm-bindgen-cli@git+file:///src/wasm-bindgen")
+            .unwrap()
+            .unwrap();
+        assert_eq!(git.url, "file:///src/wasm-bindgen");
+        assert_eq!(git.reference, None);
+        assert_eq!(git.package.as_deref(), Some("wasm-bindgen-cli"));
+        assert_eq!(git.binary, None);
+
+        let git = parse_git_spec("wasm-bindgen-cli:wasm-bindgen@git+ssh://git@host/repo")
+            .unwrap()
+            .unwrap();
+        assert_eq!(git.url, "ssh://git@host/repo");
+        assert_eq!(git.package.as_deref(), Some("wasm-bindgen-cli"));
+        assert_eq!(git.binary.as_deref(), Some("wasm-bindgen"));
+    }
+
+    #[test]
+    fn parse_git_spec_rejects_empty_parts() {
+        assert!(parse_git_spec("git+").is_err());
+        assert!(parse_git_spec("git+https://example.com/repo#").is_err());
+        assert!(parse_git_spec("@git+https://example.com/repo").is_err());
+    }
 }
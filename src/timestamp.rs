@@ -0,0 +1,184 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Seconds since the Unix epoch, negative for earlier times.
+pub fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+/// Parse an RFC 3339 timestamp such as `2024-03-01T12:30:00.123+00:00` or
+/// `2024-03-01T12:30:00Z` into seconds since the Unix epoch. Fractional seconds are
+/// dropped.
+pub fn parse_rfc3339(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (date, rest) = value.split_at_checked(10)?;
+    let days = parse_date(date)?;
+
+    let rest = rest.strip_prefix(['T', 't', ' '])?;
+    let (time, mut offset) = rest.split_at_checked(8)?;
+    let mut fields = time.split(':');
+    let hours = parse_field(fields.next()?, 2, 23)?;
+    let minutes = parse_field(fields.next()?, 2, 59)?;
+    // Allow a leap second rather than rejecting the timestamp outright
+    let seconds = parse_field(fields.next()?, 2, 60)?;
+
+    if let Some(fraction) = offset.strip_prefix('.') {
+        let digits = fraction
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(fraction.len());
+        if digits == 0 {
+            return None;
+        }
+        offset = &fraction[digits..];
+    }
+
+    let offset_secs = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match offset.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            sign * (parse_field(hours, 2, 23)? * 3600 + parse_field(minutes, 2, 59)? * 60)
+        }
+    };
+
+    Some(days * SECS_PER_DAY + hours * 3600 + minutes * 60 + seconds - offset_secs)
+}
+
+/// Parse a `YYYY-MM-DD` date into days since the Unix epoch.
+pub fn parse_date(value: &str) -> Option<i64> {
+    let mut fields = value.split('-');
+    let year = parse_field(fields.next()?, 4, 9999)?;
+    let month = parse_field(fields.next()?, 2, 12)?;
+    let day = parse_field(fields.next()?, 2, 31)?;
+    if fields.next().is_some() || month == 0 || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+/// Parse either a `YYYY-MM-DD` date, meaning midnight UTC, or an RFC 3339 timestamp.
+pub fn parse_instant(value: &str) -> Option<i64> {
+    let value = value.trim();
+    parse_date(value)
+        .map(|days| days * SECS_PER_DAY)
+        .or_else(|| parse_rfc3339(value))
+}
+
+/// Render a Unix time as `YYYY-MM-DD`, or as a full UTC timestamp when it is not
+/// midnight.
+pub fn format_instant(secs: i64) -> String {
+    let days = secs.div_euclid(SECS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let date = format!("{year:04}-{month:02}-{day:02}");
+    match secs.rem_euclid(SECS_PER_DAY) {
+        0 => date,
+        time => format!(
+            "{date}T{:02}:{:02}:{:02}Z",
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        ),
+    }
+}
+
+fn parse_field(value: &str, width: usize, max: i64) -> Option<i64> {
+    if value.len() != width || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok().filter(|number| *number <= max)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar, after Howard Hinnant's
+/// `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rfc3339_handles_offsets_and_fractions() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_rfc3339("2000-03-01T00:00:00Z"), Some(951_868_800));
+        assert_eq!(
+            parse_rfc3339("2015-12-04T00:41:52.548484+00:00"),
+            Some(1_449_189_712)
+        );
+        assert_eq!(
+            parse_rfc3339("2015-12-04T02:41:52+02:00"),
+            parse_rfc3339("2015-12-04T00:41:52Z")
+        );
+        assert_eq!(parse_rfc3339("1969-12-31T23:59:59Z"), Some(-1));
+    }
+
+    #[test]
+    fn parse_rfc3339_rejects_malformed_timestamps() {
+        assert_eq!(parse_rfc3339("2024-02-30T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2024-01-01T24:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2024-01-01T00:00:00"), None);
+        assert_eq!(parse_rfc3339("2024-01-01T00:00:00.Z"), None);
+        assert_eq!(parse_rfc3339("2024-1-01T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("yesterday"), None);
+        assert_eq!(parse_date("2024-02-29"), Some(19_782));
+        assert_eq!(parse_date("2023-02-29"), None);
+    }
+
+    #[test]
+    fn instants_round_trip_through_dates_and_timestamps() {
+        let march = parse_instant("2024-03-01").unwrap();
+        assert_eq!(Some(march), parse_rfc3339("2024-03-01T00:00:00Z"));
+        assert_eq!(format_instant(march), "2024-03-01");
+        assert_eq!(format_instant(march - 1), "2024-02-29T23:59:59Z");
+        assert_eq!(
+            parse_instant("2024-03-01T12:30:00+02:00").map(format_instant),
+            Some("2024-03-01T10:30:00Z".to_string())
+        );
+        assert_eq!(format_instant(0), "1970-01-01");
+        assert_eq!(format_instant(-SECS_PER_DAY), "1969-12-31");
+        assert_eq!(parse_instant("March 1st"), None);
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result, anyhow};
+use semver::Version;
+use std::process::Command;
+
+/// A Rust toolchain used for source builds. `name` is `None` for whatever
+/// toolchain `rustc` resolves to by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    pub name: Option<String>,
+    pub rustc: Version,
+    /// Host triple the compiler runs on and builds for by default
+    pub host: String,
+}
+
+impl Toolchain {
+    pub fn apply(&self, cmd: &mut Command) {
+        if let Some(name) = &self.name {
+            cmd.env("RUSTUP_TOOLCHAIN", name);
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{name} (rustc {})", self.rustc),
+            None => format!("default toolchain (rustc {})", self.rustc),
+        }
+    }
+}
+
+/// Choose the toolchain for a source build of a crate declaring `rust_version`.
+///
+/// An explicitly requested toolchain is used as-is, but we fail before building if it
+/// is too old. Otherwise the default toolchain is preferred, falling back to the newest
+/// installed rustup toolchain that satisfies the crate's `rust-version`.
+pub fn select_toolchain(
+    requested: Option<&str>,
+    crate_label: &str,
+    rust_version: Option<&Version>,
+) -> Result<Toolchain> {
+    if let Some(name) = requested {
+        let toolchain = query_toolchain(Some(name))?;
+        if let Some(required) = rust_version
+            && !satisfies(&toolchain.rustc, required)
+        {
+            return Err(anyhow!(
+                "{crate_label} requires rustc {required} but toolchain {name} provides rustc {}",
+                toolchain.rustc
+            ));
+        }
+        return Ok(toolchain);
+    }
+
+    let default = query_toolchain(None)?;
+    let Some(required) = rust_version else {
+        return Ok(default);
+    };
+    if satisfies(&default.rustc, required) {
+        return Ok(default);
+    }
+
+    let installed: Vec<Toolchain> = installed_toolchains()
+        .into_iter()
+        .filter_map(|name| query_toolchain(Some(&name)).ok())
+        .collect();
+
+    match pick_installed(installed, required) {
+        Some(toolchain) => {
+            eprintln!(
+                "{crate_label} requires rustc {required}; the default toolchain has rustc {}, using {}",
+                default.rustc,
+                toolchain.describe()
+            );
+            Ok(toolchain)
+        }
+        None => Err(anyhow!(
+            "{crate_label} requires rustc {required} but the default toolchain provides rustc {} \
+             and no installed toolchain is new enough; install one with `rustup toolchain install stable` \
+             or pass `+<toolchain>`",
+            default.rustc
+        )),
+    }
+}
+
+/// Parse a `rust-version` field such as `1.70` or `1.74.1` into a comparable version.
+pub fn parse_rust_version(value: &str) -> Option<Version> {
+    let mut parts = value.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |part| part.parse().ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Version::new(major, minor, patch))
+}
+
+/// Compare ignoring prerelease tags, so a `1.80.0-nightly` compiler counts as 1.80.
+fn satisfies(rustc: &Version, required: &Version) -> bool {
+    Version::new(rustc.major, rustc.minor, rustc.patch) >= *required
+}
+
+/// Prefer the newest stable compiler, only choosing prerelease channels when no
+/// stable toolchain is recent enough.
+fn pick_installed(installed: Vec<Toolchain>, required: &Version) -> Option<Toolchain> {
+    installed
+        .into_iter()
+        .filter(|toolchain| satisfies(&toolchain.rustc, required))
+        .max_by(|a, b| (a.rustc.pre.is_empty(), &a.rustc).cmp(&(b.rustc.pre.is_empty(), &b.rustc)))
+}
+
+pub fn rustc_version(toolchain: Option<&str>) -> Result<Version> {
+    Ok(query_toolchain(toolchain)?.rustc)
+}
+
+/// The triple of the machine cargox runs on, as reported by `rustc`.
+pub fn host_triple(toolchain: Option<&str>) -> Result<String> {
+    Ok(query_toolchain(toolchain)?.host)
+}
+
+/// Query `rustc -vV`, mirroring the sanitized environment source builds run in.
+fn query_toolchain(toolchain: Option<&str>) -> Result<Toolchain> {
+    let mut cmd = Command::new("rustc");
+    cmd.arg("-vV");
+    cmd.env_remove("RUSTUP_HOME");
+    cmd.env_remove("RUSTUP_TOOLCHAIN");
+    if let Some(name) = toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", name);
+    }
+
+    let output = cmd.output().context("failed to invoke rustc")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "rustc -vV failed{}: {}",
+            toolchain
+                .map(|name| format!(" for toolchain {name}"))
+                .unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (Some(rustc), Some(host)) = (parse_rustc_version(&stdout), parse_rustc_host(&stdout))
+    else {
+        return Err(anyhow!("unexpected rustc -vV output"));
+    };
+
+    Ok(Toolchain {
+        name: toolchain.map(str::to_owned),
+        rustc,
+        host,
+    })
+}
+
+fn parse_rustc_version(output: &str) -> Option<Version> {
+    let version = output.split_whitespace().nth(1)?;
+    Version::parse(version).ok()
+}
+
+fn parse_rustc_host(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.trim().to_owned())
+}
+
+fn installed_toolchains() -> Vec<String> {
+    let Ok(output) = Command::new("rustup")
+        .args(["toolchain", "list"])
+        .env_remove("RUSTUP_HOME")
+        .env_remove("RUSTUP_TOOLCHAIN")
+        .output()
+    else {
+        return vec![];
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toolchain(name: &str, rustc: &str) -> Toolchain {
+        Toolchain {
+            name: Some(name.to_string()),
+            rustc: Version::parse(rustc).unwrap(),
+            host: "x86_64-unknown-linux-gnu".to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_rust_version_pads_components() {
+        assert_eq!(parse_rust_version("1.70"), Some(Version::new(1, 70, 0)));
+        assert_eq!(parse_rust_version("1.74.1"), Some(Version::new(1, 74, 1)));
+        assert_eq!(parse_rust_version("1"), Some(Version::new(1, 0, 0)));
+        assert_eq!(parse_rust_version("1.x"), None);
+        assert_eq!(parse_rust_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn parse_rustc_version_handles_channels() {
+        assert_eq!(
+            parse_rustc_version("rustc 1.95.0 (59807616e 2026-04-14)"),
+            Some(Version::new(1, 95, 0))
+        );
+        let nightly = parse_rustc_version("rustc 1.97.0-nightly (abcdef012 2026-05-01)").unwrap();
+        assert!(satisfies(&nightly, &Version::new(1, 97, 0)));
+
+        let verbose = "rustc 1.95.0 (59807616e 2026-04-14)\nbinary: rustc\nhost: aarch64-apple-darwin\nrelease: 1.95.0\n";
+        assert_eq!(parse_rustc_version(verbose), Some(Version::new(1, 95, 0)));
+        assert_eq!(
+            parse_rustc_host(verbose).as_deref(),
+            Some("aarch64-apple-darwin")
+        );
+    }
+
+    #[test]
+    fn pick_installed_prefers_newest_stable() {
+        let installed = vec![
+            toolchain("1.70-x86_64-unknown-linux-gnu", "1.70.0"),
+            toolchain("stable-x86_64-unknown-linux-gnu", "1.95.0"),
+            toolchain("nightly-x86_64-unknown-linux-gnu", "1.97.0-nightly"),
+        ];
+        let picked = pick_installed(installed.clone(), &Version::new(1, 80, 0)).unwrap();
+        assert_eq!(
+            picked.name.as_deref(),
+            Some("stable-x86_64-unknown-linux-gnu")
+        );
+
+        let picked = pick_installed(installed.clone(), &Version::new(1, 96, 0)).unwrap();
+        assert_eq!(
+            picked.name.as_deref(),
+            Some("nightly-x86_64-unknown-linux-gnu")
+        );
+
+        assert_eq!(pick_installed(installed, &Version::new(1, 99, 0)), None);
+    }
+}
@@ -1,8 +1,9 @@
-use crate::paths::get_install_dir;
+use crate::git::short_commit;
+use crate::paths::{create_store_dir, get_install_dir};
 use anyhow::{Context, Result};
-use semver::{Version, VersionReq};
+use semver::{Op, Version, VersionReq};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct InstalledBinary {
@@ -14,17 +15,61 @@ pub fn versioned_binary_name(binary: &str, version: &Version) -> String {
     format!("{binary}-{version}")
 }
 
-pub fn versioned_binary_path(binary: &str, version: &Version) -> Result<PathBuf> {
-    let bin_dir = ensure_bin_dir()?;
-    #[cfg(windows)]
-    let path = bin_dir.join(format!("{}.exe", versioned_binary_name(binary, version)));
-    #[cfg(not(windows))]
-    let path = bin_dir.join(versioned_binary_name(binary, version));
+pub fn versioned_binary_path(
+    binary: &str,
+    version: &Version,
+    platform: Option<&str>,
+) -> Result<PathBuf> {
+    stored_binary_path(&versioned_binary_name(binary, version), platform)
+}
+
+/// Binaries built from git are keyed by commit rather than version. The `git-`
+/// infix keeps them from being mistaken for registry versions when listing.
+pub fn git_binary_name(binary: &str, commit: &str) -> String {
+    format!("{binary}-git-{}", short_commit(commit))
+}
+
+pub fn git_binary_path(binary: &str, commit: &str, platform: Option<&str>) -> Result<PathBuf> {
+    stored_binary_path(&git_binary_name(binary, commit), platform)
+}
+
+/// Binaries built from a local path are keyed by a hash of their sources.
+pub fn path_binary_name(binary: &str, source_hash: &str) -> String {
+    format!(
+        "{binary}-path-{}",
+        &source_hash[..source_hash.len().min(12)]
+    )
+}
+
+pub fn path_binary_path(
+    binary: &str,
+    source_hash: &str,
+    platform: Option<&str>,
+) -> Result<PathBuf> {
+    stored_binary_path(&path_binary_name(binary, source_hash), platform)
+}
+
+/// Host binaries live directly in `bin/`; binaries for another target triple are
+/// kept apart in `bin/<triple>/` so they are never picked up for the host.
+fn stored_binary_path(name: &str, platform: Option<&str>) -> Result<PathBuf> {
+    let bin_dir = ensure_platform_bin_dir(platform)?;
+    let windows = match platform {
+        Some(triple) => triple.contains("windows"),
+        None => cfg!(windows),
+    };
+    let path = if windows {
+        bin_dir.join(format!("{name}.exe"))
+    } else {
+        bin_dir.join(name)
+    };
     Ok(path)
 }
 
-pub fn list_installed_versions(binary: &str) -> Result<Vec<InstalledBinary>> {
-    let bin_dir = ensure_bin_dir()?;
+pub fn list_installed_versions(
+    binary: &str,
+    platform: Option<&str>,
+) -> Result<Vec<InstalledBinary>> {
+    let bin_dir = ensure_platform_bin_dir(platform)?;
     if !bin_dir.exists() {
         return Ok(vec![]);
     }
@@ -55,7 +100,6 @@ pub fn list_installed_versions(binary: &str) -> Result<Vec<InstalledBinary>> {
             None => continue,
         };
 
-        #[cfg(windows)]
         let name = name.strip_suffix(".exe").unwrap_or(name);
 
         let Some(version_str) = name.strip_prefix(&prefix) else {
@@ -73,30 +117,117 @@ pub fn list_installed_versions(binary: &str) -> Result<Vec<InstalledBinary>> {
     Ok(installed)
 }
 
+/// Whether `version` satisfies `requirement`. Semver only lets a prerelease match a
+/// requirement that names a prerelease of the same `major.minor.patch`; with `pre`,
+/// a prerelease also matches whenever its release version would, so `^2` accepts
+/// `2.1.0-beta.1`. Requirements with an `=` comparator are never widened: `=1.2.3`
+/// names the release, not its release candidates.
+pub fn version_matches(requirement: &VersionReq, version: &Version, pre: bool) -> bool {
+    requirement.matches(version)
+        || (pre
+            && !version.pre.is_empty()
+            && !requirement.comparators.iter().any(|c| c.op == Op::Exact)
+            && requirement.matches(&Version::new(version.major, version.minor, version.patch)))
+}
+
 pub fn find_installed_version(
     binary: &str,
     requirement: &VersionReq,
+    platform: Option<&str>,
+    pre: bool,
 ) -> Result<Option<InstalledBinary>> {
-    let installed = list_installed_versions(binary)?;
+    let installed = list_installed_versions(binary, platform)?;
     Ok(installed
         .into_iter()
         .rev()
-        .find(|entry| requirement.matches(&entry.version)))
+        .find(|entry| version_matches(requirement, &entry.version, pre)))
 }
 
-pub fn latest_installed(binary: &str) -> Result<Option<InstalledBinary>> {
-    let mut installed = list_installed_versions(binary)?;
-    Ok(installed.pop())
+/// The highest installed version, skipping prereleases unless `pre` is set.
+pub fn latest_installed(
+    binary: &str,
+    platform: Option<&str>,
+    pre: bool,
+) -> Result<Option<InstalledBinary>> {
+    let installed = list_installed_versions(binary, platform)?;
+    Ok(installed
+        .into_iter()
+        .rev()
+        .find(|entry| pre || entry.version.pre.is_empty()))
 }
 
 pub fn ensure_bin_dir() -> Result<PathBuf> {
     let install_dir = get_install_dir()?;
     let bin_dir = install_dir.join("bin");
-    fs::create_dir_all(&bin_dir)
+    create_store_dir(&bin_dir)
         .with_context(|| format!("failed to create binary directory {}", bin_dir.display()))?;
     Ok(bin_dir)
 }
 
+fn ensure_platform_bin_dir(platform: Option<&str>) -> Result<PathBuf> {
+    let bin_dir = ensure_bin_dir()?;
+    let Some(triple) = platform else {
+        return Ok(bin_dir);
+    };
+    let dir = bin_dir.join(triple);
+    create_store_dir(&dir)
+        .with_context(|| format!("failed to create binary directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// A file in the store, named relative to `bin/` (e.g. `aarch64-unknown-linux-gnu/tool-1.0.0`).
+#[derive(Debug, Clone)]
+pub struct StoredBinary {
+    pub name: String,
+    pub platform: Option<String>,
+    pub path: PathBuf,
+}
+
+/// Every binary in the store, host binaries first, each group sorted by name.
+pub fn list_stored_binaries() -> Result<Vec<StoredBinary>> {
+    let bin_dir = ensure_bin_dir()?;
+    let mut stored = Vec::new();
+    let mut platforms = Vec::new();
+
+    for entry in read_sorted(&bin_dir)? {
+        let file_name = entry.file_name().unwrap_or_default().to_string_lossy();
+        if entry.is_dir() {
+            platforms.push((file_name.into_owned(), entry));
+        } else if entry.is_file() {
+            stored.push(StoredBinary {
+                name: file_name.into_owned(),
+                platform: None,
+                path: entry,
+            });
+        }
+    }
+
+    for (triple, dir) in platforms {
+        for entry in read_sorted(&dir)? {
+            if entry.is_file() {
+                let file_name = entry.file_name().unwrap_or_default().to_string_lossy();
+                stored.push(StoredBinary {
+                    name: format!("{triple}/{file_name}"),
+                    platform: Some(triple.clone()),
+                    path: entry,
+                });
+            }
+        }
+    }
+
+    Ok(stored)
+}
+
+fn read_sorted(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("failed to read {}", dir.display()))?;
+    paths.sort();
+    Ok(paths)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,7 +272,7 @@ mod tests {
         let version = Version::parse("1.2.3").unwrap();
 
         with_install_dir(temp.path(), || {
-            let path = versioned_binary_path("example", &version).unwrap();
+            let path = versioned_binary_path("example", &version, None).unwrap();
             let filename = path.file_name().unwrap().to_string_lossy();
             #[cfg(windows)]
             assert_eq!(filename, "example-1.2.3.exe");
@@ -159,13 +290,30 @@ mod tests {
             fs::write(bin_dir.join("tool-0.1.0"), "").unwrap();
             fs::write(bin_dir.join("tool-0.2.0"), "").unwrap();
 
-            let versions = list_installed_versions("tool").unwrap();
+            let versions = list_installed_versions("tool", None).unwrap();
             assert_eq!(versions.len(), 2);
             assert_eq!(versions[0].version, Version::parse("0.1.0").unwrap());
             assert_eq!(versions[1].version, Version::parse("0.2.0").unwrap());
         });
     }
 
+    #[test]
+    fn list_installed_versions_skips_git_builds() {
+        let temp = tempdir().unwrap();
+
+        with_install_dir(temp.path(), || {
+            let bin_dir = ensure_bin_dir().unwrap();
+            fs::write(bin_dir.join("tool-1.0.0"), "").unwrap();
+            let git_path =
+                git_binary_path("tool", "0123456789abcdef0123456789abcdef01234567", None).unwrap();
+            fs::write(&git_path, "").unwrap();
+
+            let versions = list_installed_versions("tool", None).unwrap();
+            assert_eq!(versions.len(), 1);
+            assert_eq!(versions[0].version, Version::parse("1.0.0").unwrap());
+        });
+    }
+
     #[test]
     fn find_installed_version_respects_requirement() {
         let temp = tempdir().unwrap();
@@ -176,8 +324,117 @@ mod tests {
             fs::write(bin_dir.join("util-1.5.0"), "").unwrap();
 
             let req = VersionReq::parse("^1.0").unwrap();
-            let result = find_installed_version("util", &req).unwrap().unwrap();
+            let result = find_installed_version("util", &req, None, false)
+                .unwrap()
+                .unwrap();
             assert_eq!(result.version, Version::parse("1.5.0").unwrap());
         });
     }
+
+    #[test]
+    fn find_installed_version_skips_prereleases_of_exact_pins() {
+        let temp = tempdir().unwrap();
+
+        with_install_dir(temp.path(), || {
+            let bin_dir = ensure_bin_dir().unwrap();
+            fs::write(bin_dir.join("util-1.2.3-rc.1"), "").unwrap();
+
+            let pinned = VersionReq::parse("=1.2.3").unwrap();
+            assert!(
+                find_installed_version("util", &pinned, None, true)
+                    .unwrap()
+                    .is_none()
+            );
+            let caret = VersionReq::parse("^1.2").unwrap();
+            let found = find_installed_version("util", &caret, None, true)
+                .unwrap()
+                .unwrap();
+            assert_eq!(found.version, Version::parse("1.2.3-rc.1").unwrap());
+        });
+    }
+
+    #[test]
+    fn prerelease_and_build_metadata_versions_round_trip() {
+        let temp = tempdir().unwrap();
+
+        with_install_dir(temp.path(), || {
+            for version in ["1.9.0", "2.0.0-beta.3", "1.9.1+build.5"] {
+                let version = Version::parse(version).unwrap();
+                let path = versioned_binary_path("tool", &version, None).unwrap();
+                fs::write(path, "").unwrap();
+            }
+
+            let versions: Vec<String> = list_installed_versions("tool", None)
+                .unwrap()
+                .into_iter()
+                .map(|installed| installed.version.to_string())
+                .collect();
+            assert_eq!(versions, ["1.9.0", "1.9.1+build.5", "2.0.0-beta.3"]);
+
+            let stable = latest_installed("tool", None, false).unwrap().unwrap();
+            assert_eq!(stable.version.to_string(), "1.9.1+build.5");
+            let newest = latest_installed("tool", None, true).unwrap().unwrap();
+            assert_eq!(newest.version.to_string(), "2.0.0-beta.3");
+
+            let req = VersionReq::parse("^2").unwrap();
+            assert!(
+                find_installed_version("tool", &req, None, false)
+                    .unwrap()
+                    .is_none()
+            );
+            let found = find_installed_version("tool", &req, None, true)
+                .unwrap()
+                .unwrap();
+            assert_eq!(found.version.to_string(), "2.0.0-beta.3");
+        });
+    }
+
+    #[test]
+    fn version_matches_lets_pre_widen_requirements() {
+        let beta = Version::parse("2.1.0-beta.1").unwrap();
+        let caret = VersionReq::parse("^2").unwrap();
+        assert!(!version_matches(&caret, &beta, false));
+        assert!(version_matches(&caret, &beta, true));
+        assert!(!version_matches(
+            &VersionReq::parse("^3").unwrap(),
+            &beta,
+            true
+        ));
+        // A requirement naming a prerelease matches it without opting in
+        let named = VersionReq::parse("2.1.0-beta.1").unwrap();
+        assert!(version_matches(&named, &beta, false));
+        let pinned = VersionReq::parse("=2.1.0").unwrap();
+        assert!(!version_matches(&pinned, &beta, true));
+    }
+
+    #[test]
+    fn foreign_platform_binaries_are_stored_apart() {
+        let temp = tempdir().unwrap();
+
+        with_install_dir(temp.path(), || {
+            let version = Version::parse("1.0.0").unwrap();
+            let triple = "aarch64-unknown-linux-musl";
+            let foreign = versioned_binary_path("tool", &version, Some(triple)).unwrap();
+            assert_eq!(
+                foreign,
+                ensure_bin_dir().unwrap().join(triple).join("tool-1.0.0")
+            );
+            fs::write(&foreign, "").unwrap();
+
+            let windows =
+                versioned_binary_path("tool", &version, Some("x86_64-pc-windows-msvc")).unwrap();
+            assert!(windows.to_string_lossy().ends_with("tool-1.0.0.exe"));
+
+            assert!(list_installed_versions("tool", None).unwrap().is_empty());
+            assert_eq!(
+                list_installed_versions("tool", Some(triple)).unwrap().len(),
+                1
+            );
+
+            let stored = list_stored_binaries().unwrap();
+            assert_eq!(stored.len(), 1);
+            assert_eq!(stored[0].name, format!("{triple}/tool-1.0.0"));
+            assert_eq!(stored[0].platform.as_deref(), Some(triple));
+        });
+    }
 }
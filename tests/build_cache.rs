@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+
+use common::install_tempdir;
+
+fn run(args: &[&str], cwd: &Path, install_dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cargox"))
+        .args(args)
+        .current_dir(cwd)
+        .env("CARGOX_INSTALL_DIR", install_dir)
+        .env("CARGOX_CONFIG", cwd.join("cargox.toml"))
+        .env("CARGOX_BUILD_CACHE", "1")
+        .output()
+        .expect("Failed to execute cargox")
+}
+
+/// With the build cache enabled, source builds leave their target directory in the
+/// shared cache, which `--cache info` reports and `--cache clean` removes.
+#[test]
+fn test_build_cache_is_persistent_and_cleanable() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let crate_dir = temp.path().join("cached");
+    fs::create_dir_all(crate_dir.join("src")).unwrap();
+    fs::write(
+        crate_dir.join("Cargo.toml"),
+        "[package]\nname = \"cached\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        crate_dir.join("src/main.rs"),
+        "fn main() { println!(\"ok\"); }\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("cargox.toml"),
+        "[build-cache]\nmax-size = \"1G\"\n",
+    )
+    .unwrap();
+
+    let build = run(&["./cached"], temp.path(), install_dir.path());
+    let stderr = String::from_utf8_lossy(&build.stderr);
+    assert!(build.status.success(), "build failed:\n{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&build.stdout).trim(), "ok");
+
+    let cache_root = install_dir.path().join("cache").join("build");
+    let entries: Vec<_> = fs::read_dir(&cache_root).unwrap().collect();
+    assert_eq!(entries.len(), 1, "expected one per-toolchain target dir");
+
+    let info = run(&["--cache", "info"], temp.path(), install_dir.path());
+    let stdout = String::from_utf8_lossy(&info.stdout);
+    assert!(info.status.success());
+    assert!(stdout.contains("enabled"), "{}", stdout);
+    assert!(stdout.contains("of 1.0 GiB limit"), "{}", stdout);
+
+    let clean = run(&["--cache", "clean"], temp.path(), install_dir.path());
+    assert!(clean.status.success());
+    assert_eq!(fs::read_dir(&cache_root).unwrap().count(), 0);
+}
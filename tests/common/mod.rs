@@ -0,0 +1,13 @@
+use std::fs;
+
+/// cargox refuses to run binaries from group- or world-writable directories, so
+/// don't let a permissive umask leak into the test's install dir.
+pub fn install_tempdir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700)).unwrap();
+    }
+    dir
+}
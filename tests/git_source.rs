@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+use common::install_tempdir;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args([
+            "-c",
+            "user.name=cargox",
+            "-c",
+            "user.email=cargox@example.com",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .expect("Failed to execute git");
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+fn write_crate(dir: &Path, message: &str) {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"hello-git\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("src/main.rs"),
+        format!("fn main() {{ println!(\"{message}\"); }}\n"),
+    )
+    .unwrap();
+}
+
+/// Builds a crate from a local `file://` repository, then verifies that a rerun reuses
+/// the stored build and that a new commit on the branch triggers a rebuild.
+#[test]
+fn test_git_source_builds_and_reuses_commit() {
+    let binary_path = env!("CARGO_BIN_EXE_cargox");
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    // The binary name defaults to the repository name
+    let repo = temp.path().join("hello-git");
+
+    write_crate(&repo, "hello from git");
+    git(&repo, &["init", "--quiet", "--initial-branch=main"]);
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "--quiet", "-m", "initial"]);
+
+    let repo_url = repo.display().to_string().replace('\\', "/");
+    let spec = format!("git+file:///{}#main", repo_url.trim_start_matches('/'));
+    let run = || {
+        Command::new(binary_path)
+            .arg(&spec)
+            .env("CARGOX_INSTALL_DIR", install_dir.path())
+            .output()
+            .expect("Failed to execute cargox")
+    };
+
+    let first = run();
+    let stderr = String::from_utf8_lossy(&first.stderr);
+    assert!(first.status.success(), "first run failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&first.stdout).trim(),
+        "hello from git"
+    );
+    assert!(stderr.contains("Installing hello-git from"), "{}", stderr);
+
+    let second = run();
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(second.status.success(), "second run failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&second.stdout).trim(),
+        "hello from git"
+    );
+    assert!(
+        !stderr.contains("Installing"),
+        "rerun should reuse the stored build:\n{}",
+        stderr
+    );
+
+    write_crate(&repo, "hello again");
+    git(&repo, &["commit", "--quiet", "-am", "update"]);
+
+    let third = run();
+    let stderr = String::from_utf8_lossy(&third.stderr);
+    assert!(third.status.success(), "third run failed:\n{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&third.stdout).trim(), "hello again");
+}
+
+/// `crate:bin@git+<url>` names both the workspace crate and the binary to run in a
+/// single token, without `--bin`, and like `--bin` builds only that binary.
+#[test]
+fn test_git_spec_selects_binary_inline() {
+    let binary_path = env!("CARGO_BIN_EXE_cargox");
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let repo = temp.path().join("tools");
+
+    write_crate(&repo, "hello from git");
+    fs::create_dir_all(repo.join("src/bin")).unwrap();
+    fs::write(
+        repo.join("src/bin/greet.rs"),
+        "fn main() { println!(\"greetings from git\"); }\n",
+    )
+    .unwrap();
+    fs::write(repo.join("src/bin/broken.rs"), "fn main() { broken }\n").unwrap();
+    git(&repo, &["init", "--quiet", "--initial-branch=main"]);
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "--quiet", "-m", "initial"]);
+
+    let repo_url = repo.display().to_string().replace('\\', "/");
+    let spec = format!(
+        "hello-git:greet@git+file:///{}",
+        repo_url.trim_start_matches('/')
+    );
+    let output = Command::new(binary_path)
+        .args([&spec, "--help"])
+        .env("CARGOX_INSTALL_DIR", install_dir.path())
+        .output()
+        .expect("Failed to execute cargox");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "cargox failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "greetings from git"
+    );
+
+    let conflicting = Command::new(binary_path)
+        .args(["--bin", "hello-git", &spec])
+        .env("CARGOX_INSTALL_DIR", install_dir.path())
+        .output()
+        .expect("Failed to execute cargox");
+    assert!(!conflicting.status.success());
+    assert!(
+        String::from_utf8_lossy(&conflicting.stderr).contains("but --bin asks for `hello-git`"),
+        "{}",
+        String::from_utf8_lossy(&conflicting.stderr)
+    );
+}
@@ -118,3 +118,50 @@ fn test_bin_flag_parsing() {
         stderr
     );
 }
+
+/// Installed as `cargo-x`, cargox is run by `cargo x` with an extra leading `x`,
+/// which it drops before applying the usual passthrough rules.
+#[test]
+fn test_cargo_x_drops_the_subcommand_name() {
+    let temp = tempfile::tempdir().unwrap();
+    let cargo_x = temp
+        .path()
+        .join(format!("cargo-x{}", std::env::consts::EXE_SUFFIX));
+    std::fs::copy(env!("CARGO_BIN_EXE_cargox"), &cargo_x).unwrap();
+
+    let help = Command::new(&cargo_x)
+        .args(["x", "--help"])
+        .output()
+        .expect("Failed to execute cargo-x");
+    assert!(
+        String::from_utf8_lossy(&help.stdout).contains(HELP_SNIPPET),
+        "cargo-x x --help should show cargox's help.\nStderr:\n{}",
+        String::from_utf8_lossy(&help.stderr)
+    );
+
+    let passthrough = Command::new(&cargo_x)
+        .args(["x", "bat", "--help"])
+        .env("CARGOX_INSTALL_DIR", temp.path().join("install"))
+        .output()
+        .expect("Failed to execute cargo-x");
+    assert!(
+        !String::from_utf8_lossy(&passthrough.stdout).contains(HELP_SNIPPET),
+        "--help after the crate spec must go to the binary"
+    );
+
+    // And through cargo itself, which finds `cargo-x` on PATH
+    let mut paths = vec![temp.path().to_path_buf()];
+    paths.extend(std::env::split_paths(
+        &std::env::var_os("PATH").unwrap_or_default(),
+    ));
+    let via_cargo = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["x", "--help"])
+        .env("PATH", std::env::join_paths(paths).unwrap())
+        .output()
+        .expect("Failed to execute cargo");
+    assert!(
+        String::from_utf8_lossy(&via_cargo.stdout).contains(HELP_SNIPPET),
+        "`cargo x --help` should show cargox's help.\nStderr:\n{}",
+        String::from_utf8_lossy(&via_cargo.stderr)
+    );
+}
@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+
+use common::install_tempdir;
+
+fn write_crate(dir: &Path, body: &str) {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"hello-path\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[[bin]]\nname = \"hello\"\npath = \"src/main.rs\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("src/main.rs"), body).unwrap();
+}
+
+fn run(args: &[&str], cwd: &Path, install_dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cargox"))
+        .args(args)
+        .current_dir(cwd)
+        .env("CARGOX_INSTALL_DIR", install_dir)
+        .output()
+        .expect("Failed to execute cargox")
+}
+
+/// Builds a local crate by path, verifies the cached build is reused while sources are
+/// unchanged, and that editing a source file triggers a rebuild.
+#[test]
+fn test_path_source_caches_by_source_hash() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let crate_dir = temp.path().join("tools").join("hello");
+    write_crate(
+        &crate_dir,
+        "fn main() { println!(\"v1 {:?}\", std::env::args().skip(1).collect::<Vec<_>>()); }\n",
+    );
+
+    let first = run(
+        &["./tools/hello", "--flag"],
+        temp.path(),
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&first.stderr);
+    assert!(first.status.success(), "first run failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&first.stdout).trim(),
+        "v1 [\"--flag\"]"
+    );
+    assert!(stderr.contains("Installing hello-path from"), "{}", stderr);
+    let manifest = fs::read_to_string(install_dir.path().join("manifest.json")).unwrap();
+    assert!(manifest.contains("\"rustc\""), "{}", manifest);
+    #[cfg(target_os = "linux")]
+    assert!(manifest.contains("\"libc\""), "{}", manifest);
+
+    let second = run(
+        &["--path", "tools/hello", "--", "arg"],
+        temp.path(),
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(second.status.success(), "second run failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&second.stdout).trim(),
+        "v1 [\"arg\"]"
+    );
+    assert!(
+        !stderr.contains("Installing"),
+        "unchanged sources should reuse the cached build:\n{}",
+        stderr
+    );
+
+    write_crate(&crate_dir, "fn main() { println!(\"v2\"); }\n");
+    let third = run(&["./tools/hello"], temp.path(), install_dir.path());
+    let stderr = String::from_utf8_lossy(&third.stderr);
+    assert!(third.status.success(), "third run failed:\n{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&third.stdout).trim(), "v2");
+    assert!(stderr.contains("Installing"), "{}", stderr);
+}
+
+/// `cargox ./path -- args` passes the same arguments as `--path`, and editing a path
+/// dependency outside the crate's workspace rebuilds it.
+#[test]
+fn test_path_source_tracks_external_path_dependencies() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let greeting = temp.path().join("libs").join("greeting");
+    let write_greeting = |text: &str| {
+        fs::create_dir_all(greeting.join("src")).unwrap();
+        fs::write(
+            greeting.join("Cargo.toml"),
+            "[package]\nname = \"greeting\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        fs::write(
+            greeting.join("src/lib.rs"),
+            format!("pub const TEXT: &str = \"{text}\";\n"),
+        )
+        .unwrap();
+    };
+    write_greeting("hello");
+
+    let crate_dir = temp.path().join("tools").join("hello");
+    write_crate(
+        &crate_dir,
+        "fn main() { println!(\"{} {:?}\", greeting::TEXT, std::env::args().skip(1).collect::<Vec<_>>()); }\n",
+    );
+    let manifest = fs::read_to_string(crate_dir.join("Cargo.toml")).unwrap();
+    fs::write(
+        crate_dir.join("Cargo.toml"),
+        format!("{manifest}\n[dependencies]\ngreeting = {{ path = \"../../libs/greeting\" }}\n"),
+    )
+    .unwrap();
+
+    let first = run(
+        &["./tools/hello", "--", "arg"],
+        temp.path(),
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&first.stderr);
+    assert!(first.status.success(), "first run failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&first.stdout).trim(),
+        "hello [\"arg\"]"
+    );
+
+    write_greeting("goodbye");
+    let second = run(&["./tools/hello"], temp.path(), install_dir.path());
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(second.status.success(), "second run failed:\n{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&second.stdout).trim(), "goodbye []");
+    assert!(stderr.contains("Installing"), "{}", stderr);
+}
+
+/// Local crates have no publish dates, so `--as-of` is refused before building.
+#[test]
+fn test_path_source_rejects_as_of() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    write_crate(&temp.path().join("hello"), "fn main() {}\n");
+
+    let output = run(
+        &["--as-of", "2024-03-01", "./hello"],
+        temp.path(),
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("the crate comes from a local path"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("Installing"), "{}", stderr);
+}
+
+/// A crate whose `rust-version` no installed toolchain satisfies fails before building.
+#[test]
+fn test_path_source_rejects_unsatisfiable_rust_version() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let crate_dir = temp.path().join("future");
+    fs::create_dir_all(crate_dir.join("src")).unwrap();
+    fs::write(
+        crate_dir.join("Cargo.toml"),
+        "[package]\nname = \"future\"\nversion = \"0.1.0\"\nrust-version = \"1.999\"\n",
+    )
+    .unwrap();
+    fs::write(crate_dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    let output = run(&["./future"], temp.path(), install_dir.path());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("requires rustc 1.999.0"), "{}", stderr);
+    assert!(!stderr.contains("Installing"), "{}", stderr);
+}
+
+/// Naming the host triple with `--target` installs into the regular store, and
+/// `--list` reports the binary with its crate.
+#[test]
+fn test_path_source_with_host_target_and_list() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let crate_dir = temp.path().join("hello");
+    write_crate(&crate_dir, "fn main() { println!(\"host\"); }\n");
+
+    let rustc = Command::new("rustc").arg("-vV").output().unwrap();
+    let host = String::from_utf8_lossy(&rustc.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: ").map(str::to_owned))
+        .unwrap();
+
+    let output = run(
+        &["--target", &host, "./hello"],
+        temp.path(),
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "run failed:\n{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "host");
+
+    let list = run(&["--list"], temp.path(), install_dir.path());
+    let stdout = String::from_utf8_lossy(&list.stdout);
+    assert!(list.status.success());
+    assert!(stdout.contains("hello-path-"), "{}", stdout);
+    assert!(stdout.contains("hello-path (path+"), "{}", stdout);
+    assert!(stdout.trim_end().ends_with("host"), "{}", stdout);
+}
+
+/// With smoke tests enabled, a binary that fails its `--version` probe is rolled back
+/// instead of being run.
+#[test]
+fn test_path_source_smoke_test_rolls_back_broken_install() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    write_crate(
+        &temp.path().join("broken"),
+        "fn main() { eprintln!(\"boom\"); std::process::exit(3); }\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_cargox"))
+        .arg("./broken")
+        .current_dir(temp.path())
+        .env("CARGOX_INSTALL_DIR", install_dir.path())
+        .env("CARGOX_CONFIG", temp.path().join("missing.toml"))
+        .env("CARGOX_SMOKE_TEST", "1")
+        .output()
+        .expect("Failed to execute cargox");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("install was rolled back"), "{}", stderr);
+    assert!(stderr.contains("boom"), "{}", stderr);
+
+    let stored: Vec<_> = fs::read_dir(install_dir.path().join("bin"))
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .collect();
+    assert!(stored.is_empty(), "broken binary was kept: {:?}", stored);
+}
+
+/// When `--force` reinstalls a working binary and the new build fails its smoke test,
+/// the previous install is put back instead of being lost.
+#[test]
+fn test_failed_reinstall_keeps_previous_install() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    // The probe passes only while `healthy` exists in the working directory
+    write_crate(
+        &temp.path().join("hello"),
+        "fn main() { if !std::path::Path::new(\"healthy\").exists() { std::process::exit(3); } println!(\"ok\"); }\n",
+    );
+    let smoke_run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_cargox"))
+            .args(args)
+            .current_dir(temp.path())
+            .env("CARGOX_INSTALL_DIR", install_dir.path())
+            .env("CARGOX_CONFIG", temp.path().join("missing.toml"))
+            .env("CARGOX_SMOKE_TEST", "1")
+            .output()
+            .expect("Failed to execute cargox")
+    };
+
+    fs::write(temp.path().join("healthy"), "").unwrap();
+    let installed = smoke_run(&["./hello"]);
+    assert!(
+        installed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&installed.stderr)
+    );
+
+    fs::remove_file(temp.path().join("healthy")).unwrap();
+    let reinstalled = smoke_run(&["--force", "./hello"]);
+    let stderr = String::from_utf8_lossy(&reinstalled.stderr);
+    assert!(!reinstalled.status.success());
+    assert!(
+        stderr.contains("install was rolled back") && stderr.contains("Kept the previous install"),
+        "{}",
+        stderr
+    );
+
+    fs::write(temp.path().join("healthy"), "").unwrap();
+    let kept = run(&["./hello"], temp.path(), install_dir.path());
+    let stderr = String::from_utf8_lossy(&kept.stderr);
+    assert!(kept.status.success(), "{}", stderr);
+    assert_eq!(String::from_utf8_lossy(&kept.stdout).trim(), "ok");
+    assert!(!stderr.contains("Installing"), "{}", stderr);
+    let manifest = fs::read_to_string(install_dir.path().join("manifest.json")).unwrap();
+    assert!(manifest.contains("hello-path"), "{}", manifest);
+}
+
+/// `--paranoid` runs a binary only while it matches the checksum recorded at install time.
+#[test]
+fn test_paranoid_refuses_tampered_binary() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    write_crate(
+        &temp.path().join("hello"),
+        "fn main() { println!(\"hi\"); }\n",
+    );
+
+    let first = run(&["--paranoid", "./hello"], temp.path(), install_dir.path());
+    assert!(
+        first.status.success(),
+        "{}",
+        String::from_utf8_lossy(&first.stderr)
+    );
+
+    let stored = fs::read_dir(install_dir.path().join("bin"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.is_file())
+        .unwrap();
+    let mut contents = fs::read(&stored).unwrap();
+    contents.extend(b"tampered");
+    fs::write(&stored, contents).unwrap();
+
+    let second = run(&["--paranoid", "./hello"], temp.path(), install_dir.path());
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(!second.status.success());
+    assert!(stderr.contains("has been modified"), "{}", stderr);
+    assert!(stderr.contains("cargox --force ./hello"), "{}", stderr);
+}
+
+/// A `cargo-*` binary is started the way cargo starts external subcommands: with
+/// the subcommand name as its first argument and `CARGO` in its environment.
+#[test]
+fn test_cargo_subcommands_get_their_name_and_cargo() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let crate_dir = temp.path().join("cargo-hello");
+    fs::create_dir_all(crate_dir.join("src")).unwrap();
+    fs::write(
+        crate_dir.join("Cargo.toml"),
+        "[package]\nname = \"cargo-hello\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        crate_dir.join("src/main.rs"),
+        "fn main() {\n    let args: Vec<String> = std::env::args().skip(1).collect();\n    println!(\"{args:?} {}\", std::env::var_os(\"CARGO\").is_some());\n}\n",
+    )
+    .unwrap();
+
+    let output = run(
+        &["./cargo-hello", "check", "--all"],
+        temp.path(),
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "cargox failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "[\"hello\", \"check\", \"--all\"] true"
+    );
+
+    let named = run(
+        &["./cargo-hello", "hello", "check"],
+        temp.path(),
+        install_dir.path(),
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&named.stdout).trim(),
+        "[\"hello\", \"hello\", \"check\"] true"
+    );
+}
+
+/// On Unix cargox replaces itself with the tool, so the tool runs under cargox's PID
+/// and its exit code reaches the caller unchanged.
+#[cfg(unix)]
+#[test]
+fn test_tool_replaces_cargox_process() {
+    use std::process::Stdio;
+
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    write_crate(
+        &temp.path().join("hello"),
+        "fn main() {\n    println!(\"{}\", std::process::id());\n    std::process::exit(7);\n}\n",
+    );
+
+    let child = Command::new(env!("CARGO_BIN_EXE_cargox"))
+        .arg("./hello")
+        .current_dir(temp.path())
+        .env("CARGOX_INSTALL_DIR", install_dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute cargox");
+    let pid = child.id();
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(7), "{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        pid.to_string()
+    );
+}
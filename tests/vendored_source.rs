@@ -0,0 +1,585 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+use common::install_tempdir;
+
+fn write_vendored_crate(vendor: &Path) {
+    let dir = vendor.join("hello-vendored");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"hello-vendored\"\nversion = \"0.3.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("src/main.rs"),
+        "fn main() { println!(\"hello from vendor\"); }\n",
+    )
+    .unwrap();
+    // Directory sources list file checksums; an empty list skips verification
+    fs::write(
+        dir.join(".cargo-checksum.json"),
+        "{\"files\":{},\"package\":null}",
+    )
+    .unwrap();
+}
+
+/// Point `$CARGO_HOME` at a config that replaces crates.io with `<temp>/vendor`.
+fn vendored_cargo_home(temp: &Path) -> PathBuf {
+    let cargo_home = temp.join(".cargo");
+    fs::create_dir_all(&cargo_home).unwrap();
+    fs::write(
+        cargo_home.join("config.toml"),
+        "[source.crates-io]\nreplace-with = \"vendored\"\n\n[source.vendored]\ndirectory = \"vendor\"\n\n[net]\noffline = true\n",
+    )
+    .unwrap();
+    write_vendored_crate(&temp.join("vendor"));
+    cargo_home
+}
+
+/// Run cargox in `cwd`, reading its own config from `<cwd>/config.toml` if present.
+fn run(args: &[&str], cwd: &Path, cargo_home: &Path, install_dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_cargox"))
+        .args(args)
+        .current_dir(cwd)
+        .env("CARGO_HOME", cargo_home)
+        .env("CARGOX_INSTALL_DIR", install_dir)
+        .env("CARGOX_CONFIG", cwd.join("config.toml"))
+        .output()
+        .expect("Failed to execute cargox")
+}
+
+/// With crates.io replaced by a vendored directory in `$CARGO_HOME/config.toml`,
+/// cargox resolves the version from the directory and builds from it without any
+/// network access, even though the sandbox unsets `CARGO_HOME` for the build.
+#[test]
+fn test_vendored_directory_source_is_honored() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = vendored_cargo_home(temp.path());
+
+    let output = run(
+        &["--yes", "hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "cargox failed:\n{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "hello from vendor"
+    );
+    assert!(
+        stderr.contains("Installing hello-vendored@0.3.0 with cargo install"),
+        "{}",
+        stderr
+    );
+}
+
+/// Without a terminal, a crate cargox has never installed is refused unless a
+/// `cargox.toml` allows it; once installed it is trusted even when reinstalling, and
+/// a deny rule blocks it again.
+#[test]
+fn test_trust_policy_gates_first_install() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = vendored_cargo_home(temp.path());
+    let project_config = temp.path().join("cargox.toml");
+
+    let refused = run(
+        &["hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(!refused.status.success());
+    assert!(
+        stderr.contains("refusing to install hello-vendored") && stderr.contains("--yes"),
+        "{}",
+        stderr
+    );
+
+    fs::write(&project_config, "[trust]\nallow = [\"hello-*\"]\n").unwrap();
+    let allowed = run(
+        &["hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(
+        allowed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&allowed.stderr)
+    );
+
+    fs::remove_file(&project_config).unwrap();
+    let trusted = run(
+        &["--force", "hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(
+        trusted.status.success(),
+        "{}",
+        String::from_utf8_lossy(&trusted.stderr)
+    );
+
+    fs::write(&project_config, "[trust]\ndeny = [\"hello_vendored\"]\n").unwrap();
+    let denied = run(
+        &["hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&denied.stderr);
+    assert!(!denied.status.success());
+    assert!(stderr.contains("denied by `hello_vendored`"), "{}", stderr);
+}
+
+/// An advisory in a local RustSec database is reported when installing, and under
+/// `[advisories] strict = true` the affected version is refused even once installed.
+#[test]
+fn test_advisory_database_flags_affected_versions() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = vendored_cargo_home(temp.path());
+    let advisories = cargo_home
+        .join("advisory-db")
+        .join("crates")
+        .join("hello-vendored");
+    fs::create_dir_all(&advisories).unwrap();
+    fs::write(
+        advisories.join("RUSTSEC-2099-0001.md"),
+        "```toml\n[advisory]\nid = \"RUSTSEC-2099-0001\"\npackage = \"hello-vendored\"\ndate = \"2099-01-01\"\n\n[versions]\npatched = [\">= 0.4.0\"]\n```\n\n# Greets too loudly\n",
+    )
+    .unwrap();
+
+    let warned = run(
+        &["--yes", "hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&warned.stderr);
+    assert!(warned.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("hello-vendored 0.3.0 is affected by RUSTSEC-2099-0001")
+            && stderr.contains("no patched release of hello-vendored has been published"),
+        "{}",
+        stderr
+    );
+
+    fs::write(
+        temp.path().join("config.toml"),
+        "[advisories]\nstrict = true\n",
+    )
+    .unwrap();
+    let refused = run(
+        &["hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(!refused.status.success());
+    assert!(
+        stderr.contains("refusing to use hello-vendored 0.3.0"),
+        "{}",
+        stderr
+    );
+}
+
+/// Resolving a crate caches its registry metadata; when that metadata shows the
+/// installed version as yanked, running it warns, or fails with `refuse-yanked`
+/// unless the version is pinned exactly.
+#[test]
+fn test_yanked_installed_version_is_reported() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = vendored_cargo_home(temp.path());
+
+    let installed = run(
+        &["--yes", "hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(
+        installed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&installed.stderr)
+    );
+    let cache = install_dir
+        .path()
+        .join("cache")
+        .join("registry")
+        .join("hello-vendored.json");
+    assert!(cache.is_file(), "registry metadata was not cached");
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    fs::write(
+        &cache,
+        format!(
+            "{{\"fetched_at\":{now},\"versions\":[{{\"num\":\"0.3.0\",\"yanked\":true}},{{\"num\":\"0.2.0\",\"yanked\":false}}]}}"
+        ),
+    )
+    .unwrap();
+    let warned = run(
+        &["hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&warned.stderr);
+    assert!(warned.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("hello-vendored 0.3.0 has been yanked")
+            && stderr.contains("`cargox hello-vendored@=0.2.0`"),
+        "{}",
+        stderr
+    );
+
+    fs::write(
+        temp.path().join("config.toml"),
+        "[execute]\nrefuse-yanked = true\n",
+    )
+    .unwrap();
+    let refused = run(
+        &["hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(!refused.status.success());
+    assert!(
+        String::from_utf8_lossy(&refused.stderr).contains("refusing to run hello-vendored 0.3.0"),
+        "{}",
+        String::from_utf8_lossy(&refused.stderr)
+    );
+    let pinned = run(
+        &["hello-vendored@=0.3.0"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&pinned.stderr);
+    assert!(pinned.status.success(), "{}", stderr);
+    assert!(stderr.contains("has been yanked"), "{}", stderr);
+}
+
+/// A git-index replacement cannot be read for version lookup; cargox says so instead
+/// of querying crates.io behind the replacement's back.
+#[test]
+fn test_git_index_replacement_is_refused() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = temp.path().join(".cargo");
+    fs::create_dir_all(&cargo_home).unwrap();
+    fs::write(
+        cargo_home.join("config.toml"),
+        "[source.crates-io]\nreplace-with = \"mirror\"\n\n[source.mirror]\nregistry = \"https://git.example.invalid/index\"\n",
+    )
+    .unwrap();
+
+    let output = run(
+        &["--yes", "hello-vendored"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("replaced with the git index https://git.example.invalid/index")
+            && stderr.contains("unsupported for version lookup"),
+        "{}",
+        stderr
+    );
+}
+
+/// `@lock:<library>` runs the tool version matching the library in the nearest
+/// `Cargo.lock`, and a `[lock]` rule in `cargox.toml` does the same for a bare name
+/// unless a date bound is given.
+#[test]
+fn test_lock_spec_follows_the_project_lockfile() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = vendored_cargo_home(temp.path());
+    let project = temp.path().join("project");
+    fs::create_dir_all(project.join("src")).unwrap();
+    let lock = |version: &str| {
+        fs::write(
+            project.join("Cargo.lock"),
+            format!("version = 4\n\n[[package]]\nname = \"hello-lib\"\nversion = \"{version}\"\n"),
+        )
+        .unwrap();
+    };
+
+    lock("0.2.0");
+    let mismatched = run(
+        &["--yes", "hello-vendored@lock:hello-lib"],
+        &project.join("src"),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&mismatched.stderr);
+    assert!(!mismatched.status.success());
+    assert!(
+        stderr.contains("hello-vendored must match hello-lib 0.2.0, as locked in"),
+        "{}",
+        stderr
+    );
+
+    lock("0.3.0");
+    let matched = run(
+        &["--yes", "hello-vendored@lock:hello-lib"],
+        &project.join("src"),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&matched.stderr);
+    assert!(matched.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("Installing hello-vendored@0.3.0"),
+        "{}",
+        stderr
+    );
+
+    lock("0.2.0");
+    fs::write(
+        project.join("cargox.toml"),
+        "[lock]\nhello-vendored = \"hello-lib\"\n",
+    )
+    .unwrap();
+    let ruled = run(
+        &["hello-vendored"],
+        &project,
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(!ruled.status.success());
+    assert!(
+        String::from_utf8_lossy(&ruled.stderr).contains("must match hello-lib 0.2.0"),
+        "{}",
+        String::from_utf8_lossy(&ruled.stderr)
+    );
+
+    // A date bound overrides the rule; vendored crates carry no publish times
+    let dated = run(
+        &["hello-vendored@<2099-01-01"],
+        &project,
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&dated.stderr);
+    assert!(!dated.status.success());
+    assert!(
+        stderr.contains("known to have been published before") && !stderr.contains("must match"),
+        "{}",
+        stderr
+    );
+
+    let conflicting = run(
+        &["--as-of", "2024-03-01", "hello-vendored@lock:hello-lib"],
+        &project,
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(!conflicting.status.success());
+    assert!(
+        String::from_utf8_lossy(&conflicting.stderr).contains("cannot be combined with --as-of"),
+        "{}",
+        String::from_utf8_lossy(&conflicting.stderr)
+    );
+}
+
+/// Point `$CARGO_HOME/config.toml` at a `local-registry` in `<temp>/registry` holding
+/// `releases` of `name`, each a version, extra index fields and the message its binary
+/// prints.
+fn local_registry_cargo_home(temp: &Path, name: &str, releases: &[(&str, &str, &str)]) -> PathBuf {
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use sha2::{Digest, Sha256};
+
+    let cargo_home = temp.join(".cargo");
+    fs::create_dir_all(&cargo_home).unwrap();
+    fs::write(
+        cargo_home.join("config.toml"),
+        "[source.crates-io]\nreplace-with = \"local\"\n\n[source.local]\nlocal-registry = \"registry\"\n\n[net]\noffline = true\n",
+    )
+    .unwrap();
+
+    let registry = temp.join("registry");
+    let index = registry.join("index").join("he").join("ll");
+    fs::create_dir_all(&index).unwrap();
+    let mut entries = String::new();
+    for (version, fields, message) in releases {
+        let manifest =
+            format!("[package]\nname = \"{name}\"\nversion = \"{version}\"\nedition = \"2021\"\n");
+        let main = format!("fn main() {{ println!(\"{message}\"); }}\n");
+        let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, contents) in [("Cargo.toml", manifest), ("src/main.rs", main)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive
+                .append_data(
+                    &mut header,
+                    format!("{name}-{version}/{path}"),
+                    contents.as_bytes(),
+                )
+                .unwrap();
+        }
+        let crate_file = archive.into_inner().unwrap().finish().unwrap();
+        entries.push_str(&format!(
+            "{{\"name\":\"{name}\",\"vers\":\"{version}\",\"deps\":[],\"cksum\":\"{:x}\",\"features\":{{}},{fields}}}\n",
+            Sha256::digest(&crate_file)
+        ));
+        fs::write(registry.join(format!("{name}-{version}.crate")), crate_file).unwrap();
+    }
+    fs::write(index.join(name), entries).unwrap();
+    cargo_home
+}
+
+/// `cargo install` refuses yanked releases, so an exact pin of one that is not
+/// installed yet is built from its `.crate`; without the pin it cannot be chosen.
+#[test]
+fn test_yanked_exact_pin_installs_from_crate_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = local_registry_cargo_home(
+        temp.path(),
+        "hello-yanked",
+        &[("0.1.0", "\"yanked\":true", "hello from a yanked release")],
+    );
+
+    let unpinned = run(
+        &["--yes", "hello-yanked"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(!unpinned.status.success());
+    assert!(
+        String::from_utf8_lossy(&unpinned.stderr)
+            .contains("no published versions found for hello-yanked"),
+        "{}",
+        String::from_utf8_lossy(&unpinned.stderr)
+    );
+
+    let pinned = run(
+        &["--yes", "hello-yanked@=0.1.0"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&pinned.stderr);
+    assert!(pinned.status.success(), "cargox failed:\n{}", stderr);
+    assert!(
+        stderr.contains("hello-yanked@0.1.0 has been yanked, which cargo install refuses"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&pinned.stdout).trim(),
+        "hello from a yanked release"
+    );
+}
+
+/// Building a yanked release from a raw download needs the registry's checksum; an
+/// index entry without one is refused before anything is unpacked.
+#[test]
+fn test_yanked_exact_pin_requires_a_checksum() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = local_registry_cargo_home(
+        temp.path(),
+        "hello-yanked",
+        &[("0.1.0", "\"yanked\":true", "hello from a yanked release")],
+    );
+    fs::write(
+        temp.path().join("registry/index/he/ll/hello-yanked"),
+        "{\"name\":\"hello-yanked\",\"vers\":\"0.1.0\",\"deps\":[],\"features\":{},\"yanked\":true}\n",
+    )
+    .unwrap();
+
+    let output = run(
+        &["--yes", "hello-yanked@=0.1.0"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("the registry lists no checksum for hello-yanked 0.1.0"),
+        "{}",
+        stderr
+    );
+    assert!(output.stdout.is_empty());
+}
+
+/// A date bound resolves a release first; an installed prerelease of it is not
+/// mistaken for the release, with or without `--pre`.
+#[test]
+fn test_dated_run_skips_installed_prerelease_of_the_release() {
+    let temp = tempfile::tempdir().unwrap();
+    let install_dir = install_tempdir();
+    let cargo_home = local_registry_cargo_home(
+        temp.path(),
+        "hello-dated",
+        &[
+            (
+                "1.2.3-rc.1",
+                "\"yanked\":false,\"pubtime\":\"2023-12-01T00:00:00Z\"",
+                "release candidate",
+            ),
+            (
+                "1.2.3",
+                "\"yanked\":false,\"pubtime\":\"2024-01-01T00:00:00Z\"",
+                "release",
+            ),
+        ],
+    );
+
+    let candidate = run(
+        &["--yes", "hello-dated@=1.2.3-rc.1"],
+        temp.path(),
+        &cargo_home,
+        install_dir.path(),
+    );
+    assert!(
+        candidate.status.success(),
+        "{}",
+        String::from_utf8_lossy(&candidate.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&candidate.stdout).trim(),
+        "release candidate"
+    );
+
+    for args in [
+        &["--yes", "hello-dated@<2024-03-01"][..],
+        &["--yes", "--pre", "hello-dated@=1.2.3"][..],
+    ] {
+        let output = run(args, temp.path(), &cargo_home, install_dir.path());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{args:?}: {stderr}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "release",
+            "{args:?}: {stderr}"
+        );
+    }
+}